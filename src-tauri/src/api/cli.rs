@@ -0,0 +1,390 @@
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{
+    super::{
+        course::{export::ExportFormat, search::SearchHit, storage::ScanResult, Course, CourseMap},
+        progress::{backup::ImportMode, CourseProgress},
+    },
+    util::{self, ErrorWrapper, LoadFailure},
+    State,
+};
+
+const USAGE: &str = "Usage: portal-cli <DATA_DIR> <COMMAND>
+       portal-cli generate <EPUB_DIR>...
+
+DATA_DIR is the application data folder, containing \"User Resources\" and \"Internal Database\".
+
+Commands:
+  scan               Unpack and index User Resources, then list all Course and Course Map UUIDs
+  courses            List all Courses along with their progress
+  course-maps        List all Course Maps
+  completion <UUID>  Print the raw completion data of a Course
+  validate <UUID>    Check a Course's chapters and sections against the Tables of Contents of its textbooks
+  validate-map <UUID>
+                     Check a Course Map for duplicate items, invalid relations, and prerequisite cycles
+  render-map <UUID>  Print the SVG rendering of a Course Map, with progress displayed on top of its items
+  export-map <UUID> <FORMAT> <DEST>
+                     Export a Course Map, where FORMAT is one of dot (Graphviz), mermaid, svg, or png
+  export <UUID> <DEST> [--include-course-maps]
+                     Package a Course into an archive, optionally including the Course Maps which reference it
+  recommend [THRESHOLD]
+                     List the Courses which are ready to be studied next, where THRESHOLD (0 - 1, default 1) is the completion at which a prerequisite is considered complete
+  annotations <UUID> Print the highlights, notes, and bookmarks within a Course
+  export-annotations <UUID> <DEST>
+                     Write a Course's annotations to a Markdown file, grouped by textbook and chapter
+  search <QUERY> [LIMIT]
+                     Search the text of every textbook, listing up to LIMIT (default 20) matching documents ranked by relevance
+  progress           Print the overall progress through all Courses
+  export-progress <DEST>
+                     Back up all progress, active Courses, and settings to a JSON file
+  import-progress <SRC> [--replace]
+                     Restore a progress backup, merging it with existing progress unless --replace is given
+  profiles           List all learner profiles, along with the active profile
+
+Progress is read from the database of the active profile.
+
+generate prints a Course index (course.toml) generated from the Tables of Contents of unpacked EPUB folders.";
+
+enum Invocation {
+    Generate(Vec<PathBuf>),
+    Command(PathBuf, Command),
+}
+
+enum Command {
+    Scan,
+    Courses,
+    CourseMaps,
+    Completion(Uuid),
+    Validate(Uuid),
+    ValidateMap(Uuid),
+    RenderMap(Uuid),
+    ExportMap(Uuid, ExportFormat, PathBuf),
+    Export(Uuid, PathBuf, bool),
+    Recommend(f32),
+    Annotations(Uuid),
+    ExportAnnotations(Uuid, PathBuf),
+    Search(String, usize),
+    Progress,
+    ExportProgress(PathBuf),
+    ImportProgress(PathBuf, ImportMode),
+    Profiles,
+}
+
+impl Invocation {
+    fn parse(mut args: impl Iterator<Item = OsString>) -> Option<Self> {
+        let first = args.next()?;
+
+        if first == "generate" {
+            let paths: Vec<PathBuf> = args.map(PathBuf::from).collect();
+
+            if paths.is_empty() {
+                return None;
+            }
+
+            return Some(Self::Generate(paths));
+        }
+
+        let command = match args.next()?.to_str()? {
+            "scan" => Command::Scan,
+            "courses" => Command::Courses,
+            "course-maps" => Command::CourseMaps,
+            "completion" => Command::Completion(Uuid::try_parse(args.next()?.to_str()?).ok()?),
+            "validate" => Command::Validate(Uuid::try_parse(args.next()?.to_str()?).ok()?),
+            "validate-map" => Command::ValidateMap(Uuid::try_parse(args.next()?.to_str()?).ok()?),
+            "render-map" => Command::RenderMap(Uuid::try_parse(args.next()?.to_str()?).ok()?),
+            "export-map" => Command::ExportMap(
+                Uuid::try_parse(args.next()?.to_str()?).ok()?,
+                ExportFormat::from_name(args.next()?.to_str()?)?,
+                PathBuf::from(args.next()?),
+            ),
+            "export" => {
+                let uuid = Uuid::try_parse(args.next()?.to_str()?).ok()?;
+                let dest = PathBuf::from(args.next()?);
+
+                let include_course_maps = match args.next() {
+                    Some(flag) if flag == "--include-course-maps" => true,
+                    Some(_) => return None,
+                    None => false,
+                };
+
+                Command::Export(uuid, dest, include_course_maps)
+            }
+            "recommend" => Command::Recommend(match args.next() {
+                Some(threshold) => threshold.to_str()?.parse().ok()?,
+                None => 1.0,
+            }),
+            "annotations" => Command::Annotations(Uuid::try_parse(args.next()?.to_str()?).ok()?),
+            "export-annotations" => Command::ExportAnnotations(
+                Uuid::try_parse(args.next()?.to_str()?).ok()?,
+                PathBuf::from(args.next()?),
+            ),
+            "search" => Command::Search(
+                args.next()?.into_string().ok()?,
+                match args.next() {
+                    Some(limit) => limit.to_str()?.parse().ok()?,
+                    None => 20,
+                },
+            ),
+            "progress" => Command::Progress,
+            "export-progress" => Command::ExportProgress(PathBuf::from(args.next()?)),
+            "import-progress" => {
+                let src = PathBuf::from(args.next()?);
+
+                let mode = match args.next() {
+                    Some(flag) if flag == "--replace" => ImportMode::Replace,
+                    Some(_) => return None,
+                    None => ImportMode::Merge,
+                };
+
+                Command::ImportProgress(src, mode)
+            }
+            "profiles" => Command::Profiles,
+            _ => return None,
+        };
+
+        if args.next().is_some() {
+            return None;
+        }
+
+        Some(Self::Command(PathBuf::from(first), command))
+    }
+}
+
+/// Runs a single command without starting the application window, printing the result to stdout.
+///
+/// Commands which operate on a data folder print their results as JSON, with the exception of render-map.
+#[must_use]
+pub fn run() -> ExitCode {
+    let Some(invocation) = Invocation::parse(env::args_os().skip(1)) else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+
+    let result = match invocation {
+        Invocation::Generate(paths) => generate(&paths),
+        Invocation::Command(root, command) => {
+            let state = State::new(root);
+
+            tauri::async_runtime::block_on(async move { execute(&state, command).await })
+        }
+    };
+
+    match result {
+        Ok(output) => {
+            if writeln!(io::stdout().lock(), "{output}").is_err() {
+                return ExitCode::FAILURE;
+            }
+
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!(
+                "{}",
+                serde_json::to_string_pretty(&error).unwrap_or(error.message)
+            );
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn generate(paths: &[PathBuf]) -> Result<String, ErrorWrapper> {
+    let course = Course::generate(paths)
+        .map_err(|e| ErrorWrapper::new("Unable to read textbook".to_string(), &e))?;
+
+    toml::to_string_pretty(&course)
+        .map_err(|e| ErrorWrapper::new("Unable to serialize Course index".to_string(), &e))
+}
+
+async fn execute(state: &State, command: Command) -> Result<String, ErrorWrapper> {
+    match command {
+        Command::Scan => to_json(&scan(state).await?),
+        Command::Courses => to_json(&courses(state).await?),
+        Command::CourseMaps => to_json(&course_maps(state).await?),
+        Command::Completion(uuid) => {
+            let (_, completion, _) = util::get_course(state, uuid).await?;
+
+            to_json(&completion)
+        }
+        Command::Validate(uuid) => to_json(
+            &state
+                .get_datastore()
+                .await?
+                .validate_course(uuid)
+                .await
+                .map_err(|e| ErrorWrapper::new(format!("Unable to validate Course {uuid}"), &e))?,
+        ),
+        Command::ValidateMap(uuid) => {
+            let (_, _, _, diagnostics) = state
+                .get_datastore()
+                .await?
+                .get_course_map(uuid)
+                .await
+                .map_err(|e| ErrorWrapper::new(format!("Unable to get Course Map {uuid}"), &e))?;
+
+            to_json(&diagnostics)
+        }
+        Command::RenderMap(uuid) => {
+            let (rendered, error) = util::get_course_map_progress(state, uuid).await?;
+
+            if let Some(error) = error {
+                eprintln!("{error}");
+            }
+
+            Ok(rendered)
+        }
+        Command::ExportMap(uuid, format, dest) => {
+            let error = state
+                .get_datastore()
+                .await?
+                .export_course_map(uuid, format, dest.clone())
+                .await
+                .map_err(|e| {
+                    ErrorWrapper::new(format!("Unable to export Course Map {uuid}"), &e)
+                })?;
+
+            if let Some(error) = error {
+                eprintln!("{error}");
+            }
+
+            to_json(&dest)
+        }
+        Command::Export(uuid, dest, include_course_maps) => {
+            state
+                .get_datastore()
+                .await?
+                .export_course(uuid, dest.clone(), include_course_maps)
+                .await
+                .map_err(|e| ErrorWrapper::new(format!("Unable to export Course {uuid}"), &e))?;
+
+            to_json(&dest)
+        }
+        Command::Recommend(threshold) => to_json(
+            &util::get_recommended(state, threshold)
+                .await?
+                .into_iter()
+                .map(|(course, progress)| CourseOutput { course, progress })
+                .collect::<Vec<_>>(),
+        ),
+        Command::Annotations(uuid) => {
+            let (_, annotations) = util::get_annotations(state, uuid).await?;
+
+            to_json(&annotations)
+        }
+        Command::ExportAnnotations(uuid, dest) => {
+            util::export_annotations(state, uuid, dest.clone()).await?;
+
+            to_json(&dest)
+        }
+        Command::Search(query, limit) => to_json(&search(state, query, limit).await?),
+        Command::Progress => to_json(
+            &state
+                .get_database()
+                .await?
+                .get_overall_progress()
+                .await
+                .map_err(|e| ErrorWrapper::new("Unable to get overall progress".to_string(), &e))?,
+        ),
+        Command::ExportProgress(dest) => {
+            state.export_progress(&dest).await?;
+
+            to_json(&dest)
+        }
+        Command::ImportProgress(src, mode) => {
+            let data = tokio::fs::read_to_string(&src)
+                .await
+                .map_err(|e| ErrorWrapper::new("Unable to read progress backup".to_string(), &e))?;
+
+            state.import_progress(&data, mode).await?;
+
+            to_json(&src)
+        }
+        Command::Profiles => to_json(&state.get_profiles().await?),
+    }
+}
+
+/// Prints resources which couldn't be loaded to stderr, so that they don't prevent listing the remaining resources.
+fn report_failures(failures: &[LoadFailure]) {
+    for failure in failures {
+        if let Ok(failure) = serde_json::to_string(failure) {
+            eprintln!("{failure}");
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, ErrorWrapper> {
+    serde_json::to_string_pretty(value)
+        .map_err(|e| ErrorWrapper::new("Unable to serialize output".to_string(), &e))
+}
+
+async fn scan(state: &State) -> Result<ScanResult, ErrorWrapper> {
+    let threads = state.get_threads().await;
+
+    state
+        .get_datastore()
+        .await?
+        .scan(threads)
+        .await
+        .map_err(|e| ErrorWrapper::new("Unable to get Course and CourseMap list".to_string(), &e))
+}
+
+/// Scans the resource folder and updates the search index before searching it, as the CLI doesn't keep the index up to date in the background.
+async fn search(
+    state: &State,
+    query: String,
+    limit: usize,
+) -> Result<Vec<SearchHit>, ErrorWrapper> {
+    scan(state).await?;
+
+    // Textbooks which were indexed previously can still be searched
+    if let Err(error) = util::update_search_index(state).await {
+        if let Ok(error) = serde_json::to_string(&error) {
+            eprintln!("{error}");
+        }
+    }
+
+    util::search(state, query, limit).await
+}
+
+async fn courses(state: &State) -> Result<Vec<CourseOutput>, ErrorWrapper> {
+    let scan = scan(state).await?;
+    let threads = state.get_threads().await;
+
+    let (courses, failures) = util::get_courses(state, &scan.courses, threads).await?;
+
+    report_failures(&failures);
+
+    Ok(courses
+        .into_iter()
+        .map(|(course, progress)| CourseOutput { course, progress })
+        .collect())
+}
+
+#[derive(Serialize)]
+struct CourseOutput {
+    course: Course,
+    progress: CourseProgress,
+}
+
+async fn course_maps(state: &State) -> Result<Vec<CourseMap>, ErrorWrapper> {
+    let scan = scan(state).await?;
+    let threads = state.get_threads().await;
+
+    let (course_maps, failures) = util::get_course_maps(state, &scan.course_maps, threads).await?;
+
+    report_failures(&failures);
+
+    Ok(course_maps
+        .into_iter()
+        .map(|(course_map, _, _, _)| course_map)
+        .collect())
+}
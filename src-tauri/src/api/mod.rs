@@ -1,36 +1,74 @@
 #![allow(clippy::used_underscore_binding)]
 
-use std::path::PathBuf;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+    sync::{self, OnceLock, PoisonError},
+};
 
 use chrono::{Local, NaiveDate};
 use schemars::schema_for;
 use serde::Serialize;
-use tokio::{runtime::Handle, sync::OnceCell, task, try_join};
+use tauri::{AppHandle, Manager};
+use tokio::{
+    runtime::Handle,
+    sync::{MappedMutexGuard, Mutex, MutexGuard, OnceCell},
+    task, try_join,
+};
 use uuid::Uuid;
 
+pub mod cli;
 mod util;
+pub mod watcher;
 
-use util::ErrorWrapper;
+use util::{ErrorWrapper, LoadFailure};
 
 use super::{
-    course::{storage::DataStore, Course, CourseMap},
-    progress::{database::Database, CourseCompletion, CourseProgress, OverallProgress},
+    course::{
+        search::SearchHit,
+        storage::{ArchiveImport, DataStore, RenderedCourseMap},
+        svg::{RenderError, DEFAULT_RENDER_TIMEOUT},
+        validation::CourseDiagnostic,
+        Course, CourseMap,
+    },
+    progress::{
+        annotations::Annotation,
+        backup::{ImportMode, ProgressBackup},
+        database::Database,
+        profiles::{Profile, ProfileRegistry},
+        CourseCompletion, CourseProgress, OverallProgress,
+    },
 };
 
 pub struct State {
     root: PathBuf,
-    database: OnceCell<Database>,
+    session: Mutex<Option<Session>>,
     datastore: OnceCell<DataStore>,
     threads: OnceCell<usize>,
+    /// The reason the resource folder isn't being watched for changes, if watching it failed
+    watch_error: OnceLock<ErrorWrapper>,
+    /// The reason the last update to the search index failed, if it did
+    search_error: sync::Mutex<Option<ErrorWrapper>>,
+}
+
+/// The profile registry, along with the database of each profile which has been active
+struct Session {
+    registry: ProfileRegistry,
+    /// Databases stay open once loaded, as a database can't be reopened while commands started before switching profiles still hold it
+    ///
+    /// This always contains the database of the active profile.
+    databases: HashMap<Uuid, Database>,
 }
 
 impl State {
     pub fn new(root: PathBuf) -> Self {
         Self {
             root,
-            database: OnceCell::new(),
+            session: Mutex::new(None),
             datastore: OnceCell::new(),
             threads: OnceCell::new(),
+            watch_error: OnceLock::new(),
+            search_error: sync::Mutex::new(None),
         }
     }
 
@@ -49,6 +87,8 @@ impl State {
             .get_or_try_init(|| async {
                 let datastore_path = self.root.join("User Resources");
                 let schema_path = self.root.join("Resource Schema");
+                let cache_path = self.root.join("Render Cache");
+                let search_path = self.root.join("Search Index");
 
                 task::spawn_blocking(move || {
                     std::fs::create_dir_all(&datastore_path).map_err(|e| {
@@ -84,29 +124,205 @@ impl State {
                         ErrorWrapper::new("Unable to write resource schema".to_string(), &e)
                     })?;
 
-                    Ok(DataStore::new(datastore_path))
+                    Ok(DataStore::new(
+                        datastore_path,
+                        cache_path,
+                        search_path,
+                        DEFAULT_RENDER_TIMEOUT,
+                    ))
                 })
                 .await?
             })
             .await
     }
 
-    pub async fn get_database(&self) -> Result<&Database, ErrorWrapper> {
-        self.database
-            .get_or_try_init(|| async {
-                let database_path = self.root.join("Internal Database");
+    /// Returns problems which don't prevent resources from being listed, but which are reported alongside them.
+    fn warnings(&self) -> Vec<ErrorWrapper> {
+        let search_error = self
+            .search_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        self.watch_error
+            .get()
+            .cloned()
+            .into_iter()
+            .chain(search_error)
+            .collect()
+    }
 
-                task::spawn_blocking(move || {
-                    Database::new(&database_path).map_err(|e| {
-                        ErrorWrapper::new("Unable to load application database".to_string(), &e)
-                    })
-                })
-                .await?
-            })
+    /// Returns the database of the active profile.
+    ///
+    /// The returned database remains usable if the active profile is switched, but further calls will return the new profile's database.
+    pub async fn get_database(&self) -> Result<Database, ErrorWrapper> {
+        let session = self.get_session().await?;
+
+        Ok(session.databases[&session.registry.active].clone())
+    }
+
+    async fn get_session(&self) -> Result<MappedMutexGuard<'_, Session>, ErrorWrapper> {
+        let mut session = self.session.lock().await;
+
+        if session.is_none() {
+            let registry = ProfileRegistry::load(self.root.clone())
+                .await
+                .map_err(|e| ErrorWrapper::new("Unable to load profiles".to_string(), &e))?;
+            let database = open_database(&self.root, registry.active).await?;
+
+            *session = Some(Session {
+                databases: HashMap::from([(registry.active, database)]),
+                registry,
+            });
+        }
+
+        Ok(MutexGuard::map(session, |session| {
+            session.as_mut().unwrap()
+        }))
+    }
+
+    pub async fn get_profiles(&self) -> Result<ProfileRegistry, ErrorWrapper> {
+        Ok(self.get_session().await?.registry.clone())
+    }
+
+    pub async fn create_profile(&self, name: &str) -> Result<Profile, ErrorWrapper> {
+        let mut session = self.get_session().await?;
+
+        let mut registry = session.registry.clone();
+        let profile = registry
+            .create(name)
+            .map_err(|e| ErrorWrapper::new("Unable to create profile".to_string(), &e))?;
+
+        registry
+            .save(self.root.clone())
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to save profiles".to_string(), &e))?;
+
+        session.registry = registry;
+
+        Ok(profile)
+    }
+
+    /// Makes another profile the active profile, without requiring the application to be restarted.
+    pub async fn switch_profile(&self, id: Uuid) -> Result<(), ErrorWrapper> {
+        let mut session = self.get_session().await?;
+
+        if session.registry.active == id {
+            return Ok(());
+        }
+
+        if session.registry.get(id).is_none() {
+            return Err(ErrorWrapper {
+                message: "Unable to switch profile".to_string(),
+                cause: format!("Profile {id} does not exist"),
+            });
+        }
+
+        if let Entry::Vacant(entry) = session.databases.entry(id) {
+            entry.insert(open_database(&self.root, id).await?);
+        }
+
+        let mut registry = session.registry.clone();
+        registry.active = id;
+
+        registry
+            .save(self.root.clone())
             .await
+            .map_err(|e| ErrorWrapper::new("Unable to save profiles".to_string(), &e))?;
+
+        session.registry = registry;
+
+        Ok(())
+    }
+
+    pub async fn rename_profile(&self, id: Uuid, name: &str) -> Result<(), ErrorWrapper> {
+        let mut session = self.get_session().await?;
+
+        let mut registry = session.registry.clone();
+        registry
+            .rename(id, name)
+            .map_err(|e| ErrorWrapper::new("Unable to rename profile".to_string(), &e))?;
+
+        registry
+            .save(self.root.clone())
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to save profiles".to_string(), &e))?;
+
+        session.registry = registry;
+
+        Ok(())
+    }
+
+    /// Deletes a profile which isn't active, along with all of its progress and settings.
+    pub async fn delete_profile(&self, id: Uuid) -> Result<(), ErrorWrapper> {
+        let mut session = self.get_session().await?;
+
+        let mut registry = session.registry.clone();
+        registry
+            .remove(id)
+            .map_err(|e| ErrorWrapper::new("Unable to delete profile".to_string(), &e))?;
+
+        // Only the session holds the databases of inactive profiles, so this closes the database before its files are removed
+        if let Some(database) = session.databases.remove(&id) {
+            database.close().await.map_err(|e| {
+                ErrorWrapper::new("Unable to close profile database".to_string(), &e)
+            })?;
+        }
+
+        // The profile is kept if its database can't be deleted, so that deleting it can be retried
+        ProfileRegistry::delete_database(self.root.clone(), id)
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to delete profile database".to_string(), &e))?;
+
+        registry
+            .save(self.root.clone())
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to save profiles".to_string(), &e))?;
+
+        session.registry = registry;
+
+        Ok(())
+    }
+    /// Writes a backup of the active profile's progress to `dest`.
+    pub async fn export_progress(&self, dest: &Path) -> Result<(), ErrorWrapper> {
+        let backup = self
+            .get_database()
+            .await?
+            .export_progress()
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to read progress".to_string(), &e))?;
+
+        let data = backup
+            .to_json()
+            .map_err(|e| ErrorWrapper::new("Unable to serialize progress".to_string(), &e))?;
+
+        tokio::fs::write(dest, data)
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to write progress backup".to_string(), &e))
+    }
+    /// Imports a progress backup into the active profile.
+    pub async fn import_progress(&self, data: &str, mode: ImportMode) -> Result<(), ErrorWrapper> {
+        let backup = ProgressBackup::from_json(data)
+            .map_err(|e| ErrorWrapper::new("Unable to parse progress backup".to_string(), &e))?;
+
+        self.get_database()
+            .await?
+            .import_progress(backup, mode)
+            .await
+            .map_err(|e| ErrorWrapper::new("Unable to import progress".to_string(), &e))
     }
 }
 
+async fn open_database(root: &Path, id: Uuid) -> Result<Database, ErrorWrapper> {
+    let database_path = ProfileRegistry::database_path(root, id);
+
+    task::spawn_blocking(move || {
+        Database::new(&database_path)
+            .map_err(|e| ErrorWrapper::new("Unable to load application database".to_string(), &e))
+    })
+    .await?
+}
+
 #[tauri::command]
 #[allow(clippy::needless_pass_by_value)]
 pub fn get_data_dir(state: tauri::State<'_, State>) -> PathBuf {
@@ -119,6 +335,12 @@ pub fn get_internal_data_dir(state: tauri::State<'_, State>) -> PathBuf {
     state.root.clone()
 }
 
+#[tauri::command]
+#[allow(clippy::needless_pass_by_value)]
+pub fn get_export_dir(state: tauri::State<'_, State>) -> PathBuf {
+    state.root.join("Exported Resources")
+}
+
 #[tauri::command]
 pub fn get_backend_date() -> NaiveDate {
     Local::now().date_naive()
@@ -134,6 +356,47 @@ pub async fn get_course(
     Ok((course, completion))
 }
 
+#[tauri::command]
+pub async fn validate_course(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+) -> Result<Vec<CourseDiagnostic>, ErrorWrapper> {
+    state
+        .get_datastore()
+        .await?
+        .validate_course(uuid)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to validate Course {uuid}"), &e))
+}
+
+#[tauri::command]
+pub async fn export_course(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+    include_course_maps: bool,
+) -> Result<PathBuf, ErrorWrapper> {
+    let datastore = state.get_datastore().await?;
+
+    let course = datastore
+        .get_course(uuid)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to get Course {uuid}"), &e))?;
+
+    let export_path = state.root.join("Exported Resources");
+    let dest = export_path.join(util::to_filename(&course.title, ".Portal.zip"));
+
+    tokio::fs::create_dir_all(&export_path)
+        .await
+        .map_err(|e| ErrorWrapper::new("Unable to create export folder".to_string(), &e))?;
+
+    datastore
+        .export_course(uuid, dest.clone(), include_course_maps)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to export Course {uuid}"), &e))?;
+
+    Ok(dest)
+}
+
 #[tauri::command]
 pub async fn set_course_completion(
     state: tauri::State<'_, State>,
@@ -153,6 +416,84 @@ pub async fn set_course_completion(
         .map_err(|e| ErrorWrapper::new(format!("Unable to update progress for Course {uuid}"), &e))
 }
 
+#[tauri::command]
+pub async fn get_annotations(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+) -> Result<Vec<Annotation>, ErrorWrapper> {
+    let (_, annotations) = util::get_annotations(&state, uuid).await?;
+
+    Ok(annotations)
+}
+
+#[tauri::command]
+pub async fn create_annotation(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+    annotation: Annotation,
+) -> Result<Annotation, ErrorWrapper> {
+    state
+        .get_database()
+        .await?
+        .create_annotation(uuid, annotation)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to create annotation in Course {uuid}"), &e))
+}
+
+#[tauri::command]
+pub async fn update_annotation(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+    annotation: Annotation,
+) -> Result<Annotation, ErrorWrapper> {
+    let id = annotation.uuid;
+
+    state
+        .get_database()
+        .await?
+        .update_annotation(uuid, annotation)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to update annotation {id}"), &e))
+}
+
+#[tauri::command]
+pub async fn delete_annotation(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+    annotation: Uuid,
+) -> Result<(), ErrorWrapper> {
+    state
+        .get_database()
+        .await?
+        .delete_annotation(uuid, annotation)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to delete annotation {annotation}"), &e))
+}
+
+#[tauri::command]
+pub async fn export_annotations(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+) -> Result<PathBuf, ErrorWrapper> {
+    let course = state
+        .get_datastore()
+        .await?
+        .get_course(uuid)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to get Course {uuid}"), &e))?;
+
+    let export_path = state.root.join("Exported Resources");
+    let dest = export_path.join(util::to_filename(&course.title, ".Notes.md"));
+
+    tokio::fs::create_dir_all(&export_path)
+        .await
+        .map_err(|e| ErrorWrapper::new("Unable to create export folder".to_string(), &e))?;
+
+    util::export_annotations(&state, uuid, dest.clone()).await?;
+
+    Ok(dest)
+}
+
 #[tauri::command]
 pub async fn get_active_courses(state: tauri::State<'_, State>) -> Result<Vec<Uuid>, ErrorWrapper> {
     util::get_active_courses(&state).await
@@ -172,7 +513,10 @@ pub async fn set_active_courses(
 }
 
 #[tauri::command]
-pub async fn get_all(state: tauri::State<'_, State>) -> Result<ListingResult, ErrorWrapper> {
+pub async fn get_all(
+    app: AppHandle,
+    state: tauri::State<'_, State>,
+) -> Result<ListingResult, ErrorWrapper> {
     let threads = state.get_threads().await;
     let scan = state
         .get_datastore()
@@ -183,21 +527,42 @@ pub async fn get_all(state: tauri::State<'_, State>) -> Result<ListingResult, Er
             ErrorWrapper::new("Unable to get Course and CourseMap list".to_string(), &e)
         })?;
 
-    let (courses, course_maps) = try_join!(
+    // Indexing textbooks can take much longer than listing them, so it's done in the background. Failures are reported by the next listing
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<State>();
+        let result = util::update_search_index(&state).await;
+
+        *state
+            .search_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = result.err();
+    });
+
+    let ((courses, mut failures), (course_maps, mut course_map_failures)) = try_join!(
         util::get_courses(&state, &scan.courses, threads),
         util::get_course_maps(&state, &scan.course_maps, threads)
     )?;
 
+    failures.append(&mut course_map_failures);
+
     Ok(ListingResult {
         courses,
         course_maps,
+        imports: scan.imports,
+        failures,
+        warnings: state.warnings(),
     })
 }
 
 #[derive(Serialize)]
 pub struct ListingResult {
     courses: Vec<(Course, CourseProgress)>,
-    course_maps: Vec<(CourseMap, String)>,
+    course_maps: Vec<RenderedCourseMap>,
+    imports: Vec<ArchiveImport>,
+    /// Resources which were found, but could not be loaded
+    failures: Vec<LoadFailure>,
+    /// Problems which don't affect any particular resource
+    warnings: Vec<ErrorWrapper>,
 }
 
 #[tauri::command]
@@ -206,7 +571,39 @@ pub async fn get_active(
 ) -> Result<Vec<(Course, CourseProgress)>, ErrorWrapper> {
     let scan = util::get_active_courses(&state).await?;
     let threads = state.get_threads().await;
-    util::get_courses(&state, &scan, threads).await
+
+    // Courses which fail to load are already reported by the Course Navigator
+    let (courses, _) = util::get_courses(&state, &scan, threads).await?;
+
+    Ok(courses)
+}
+
+/// Returns the Courses which are ready to be studied next, where `threshold` is the completion (ranging between 0 and 1) at which a prerequisite is considered complete.
+#[tauri::command]
+pub async fn get_recommended(
+    state: tauri::State<'_, State>,
+    threshold: f32,
+) -> Result<Vec<(Course, CourseProgress)>, ErrorWrapper> {
+    util::get_recommended(&state, threshold).await
+}
+
+/// Searches the text of every textbook in the resource folder, returning up to `limit` matching content documents ranked by relevance.
+#[tauri::command]
+pub async fn search_textbooks(
+    state: tauri::State<'_, State>,
+    query: String,
+    limit: usize,
+) -> Result<Vec<SearchHit>, ErrorWrapper> {
+    util::search(&state, query, limit).await
+}
+
+/// Renders a Course Map with the learner's progress displayed on top of its items, along with the reason it had to be rendered as a grid (if any).
+#[tauri::command]
+pub async fn get_course_map_progress(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+) -> Result<(String, Option<RenderError>), ErrorWrapper> {
+    util::get_course_map_progress(&state, uuid).await
 }
 
 #[tauri::command]
@@ -243,3 +640,72 @@ pub async fn set_settings(
         .await
         .map_err(|e| ErrorWrapper::new("Unable to update Settings".to_string(), &e))
 }
+
+#[tauri::command]
+pub async fn get_profiles(state: tauri::State<'_, State>) -> Result<ProfileRegistry, ErrorWrapper> {
+    state.get_profiles().await
+}
+
+#[tauri::command]
+pub async fn create_profile(
+    state: tauri::State<'_, State>,
+    name: String,
+) -> Result<Profile, ErrorWrapper> {
+    state.create_profile(&name).await
+}
+
+#[tauri::command]
+pub async fn switch_profile(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+) -> Result<(), ErrorWrapper> {
+    state.switch_profile(uuid).await
+}
+
+#[tauri::command]
+pub async fn rename_profile(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+    name: String,
+) -> Result<(), ErrorWrapper> {
+    state.rename_profile(uuid, &name).await
+}
+
+#[tauri::command]
+pub async fn delete_profile(
+    state: tauri::State<'_, State>,
+    uuid: Uuid,
+) -> Result<(), ErrorWrapper> {
+    state.delete_profile(uuid).await
+}
+
+#[tauri::command]
+pub async fn export_progress(state: tauri::State<'_, State>) -> Result<PathBuf, ErrorWrapper> {
+    let registry = state.get_profiles().await?;
+    let name = registry
+        .get(registry.active)
+        .map_or("Progress", |profile| profile.name.as_str());
+
+    let export_path = state.root.join("Exported Resources");
+    let dest = export_path.join(util::to_filename(
+        &format!("{name} {}", Local::now().date_naive()),
+        ".Portal-Progress.json",
+    ));
+
+    tokio::fs::create_dir_all(&export_path)
+        .await
+        .map_err(|e| ErrorWrapper::new("Unable to create export folder".to_string(), &e))?;
+
+    state.export_progress(&dest).await?;
+
+    Ok(dest)
+}
+
+#[tauri::command]
+pub async fn import_progress(
+    state: tauri::State<'_, State>,
+    data: String,
+    mode: ImportMode,
+) -> Result<(), ErrorWrapper> {
+    state.import_progress(&data, mode).await
+}
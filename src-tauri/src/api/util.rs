@@ -1,17 +1,33 @@
-use futures_util::future::try_join_all;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use futures_util::future::{join_all, try_join_all};
 use serde::Serialize;
-use tokio::task::JoinError;
+use tokio::{
+    task::{self, JoinError},
+    try_join,
+};
 use uuid::Uuid;
 
 use super::{
     super::{
-        course::{Course, CourseMap},
-        progress::{CourseCompletion, CourseProgress},
+        course::{
+            search::SearchHit,
+            storage::{RenderedCourseMap, ResourceKind},
+            svg::{CourseMapProgress, RenderError},
+            Course, CourseMap,
+        },
+        progress::{
+            annotations::{self, Annotation},
+            recommendation, CourseCompletion, CourseProgress,
+        },
     },
     State,
 };
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct ErrorWrapper {
     pub(super) message: String,
     pub(super) cause: String,
@@ -29,12 +45,43 @@ impl ErrorWrapper {
     }
 }
 
+/// A resource which could not be loaded, which is reported alongside the resources which were loaded successfully
+#[derive(Serialize)]
+pub struct LoadFailure {
+    pub(super) uuid: Uuid,
+    pub(super) kind: ResourceKind,
+    /// The path of the resource's index
+    pub(super) path: PathBuf,
+    pub(super) error: ErrorWrapper,
+}
+
 impl From<JoinError> for ErrorWrapper {
     fn from(value: JoinError) -> Self {
         Self::new("An internal error occured".to_string(), &value)
     }
 }
 
+/// Converts a resource title into a filename which is valid on all supported platforms.
+pub(super) fn to_filename(title: &str, extension: &str) -> String {
+    let mut filename: String = title
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    filename = filename.trim().trim_end_matches('.').to_string();
+
+    if filename.is_empty() {
+        filename.push_str("Untitled");
+    }
+
+    filename.push_str(extension);
+    filename
+}
+
 pub(super) async fn get_course(
     state: &State,
     id: Uuid,
@@ -58,8 +105,11 @@ pub(super) async fn get_courses(
     state: &State,
     ids: &[Uuid],
     threads: usize,
-) -> Result<Vec<(Course, CourseProgress)>, ErrorWrapper> {
+) -> Result<(Vec<(Course, CourseProgress)>, Vec<LoadFailure>), ErrorWrapper> {
     let mut hydrated_courses = Vec::with_capacity(ids.len());
+    let mut failures = Vec::new();
+
+    let datastore = state.get_datastore().await?;
 
     for chunk in ids.chunks(threads) {
         let mut future_set = Vec::with_capacity(threads);
@@ -68,14 +118,22 @@ pub(super) async fn get_courses(
             future_set.push(get_course(state, *uuid));
         }
 
-        let results = try_join_all(future_set).await?;
+        let results = join_all(future_set).await;
 
-        for result in results {
-            hydrated_courses.push((result.0, result.2));
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(result) => hydrated_courses.push((result.0, result.2)),
+                Err(error) => failures.push(LoadFailure {
+                    uuid: chunk[index],
+                    kind: ResourceKind::Course,
+                    path: datastore.course_path(chunk[index]),
+                    error,
+                }),
+            }
         }
     }
 
-    Ok(hydrated_courses)
+    Ok((hydrated_courses, failures))
 }
 
 pub(super) async fn get_active_courses(state: &State) -> Result<Vec<Uuid>, ErrorWrapper> {
@@ -116,8 +174,9 @@ pub(super) async fn get_course_maps(
     state: &State,
     ids: &[Uuid],
     threads: usize,
-) -> Result<Vec<(CourseMap, String)>, ErrorWrapper> {
+) -> Result<(Vec<RenderedCourseMap>, Vec<LoadFailure>), ErrorWrapper> {
     let mut course_maps = Vec::with_capacity(ids.len());
+    let mut failures = Vec::new();
 
     let datastore = state.get_datastore().await?;
 
@@ -125,20 +184,179 @@ pub(super) async fn get_course_maps(
         let mut future_set = Vec::with_capacity(threads);
 
         for uuid in chunk {
-            future_set.push(async {
-                let uuid = *uuid;
-
-                datastore
-                    .get_course_map(uuid)
-                    .await
-                    .map_err(|e| ErrorWrapper::new(format!("Unable to get Course Map {uuid}"), &e))
-            });
+            future_set.push(datastore.get_course_map(*uuid));
+        }
+
+        let results = join_all(future_set).await;
+
+        for (index, result) in results.into_iter().enumerate() {
+            let uuid = chunk[index];
+
+            match result {
+                Ok(result) => course_maps.push(result),
+                Err(e) => failures.push(LoadFailure {
+                    uuid,
+                    kind: ResourceKind::CourseMap,
+                    path: datastore.course_map_path(uuid),
+                    error: ErrorWrapper::new(format!("Unable to get Course Map {uuid}"), &e),
+                }),
+            }
+        }
+    }
+
+    Ok((course_maps, failures))
+}
+
+pub(super) async fn get_recommended(
+    state: &State,
+    threshold: f32,
+) -> Result<Vec<(Course, CourseProgress)>, ErrorWrapper> {
+    let threads = state.get_threads().await;
+    let datastore = state.get_datastore().await?;
+    let (course_ids, course_map_ids) = datastore.list(threads).await.map_err(|e| {
+        ErrorWrapper::new("Unable to get Course and CourseMap list".to_string(), &e)
+    })?;
+
+    let ((courses, _), active) = try_join!(
+        get_courses(state, &course_ids, threads),
+        get_active_courses(state)
+    )?;
+
+    // Course Maps which fail to load are already reported by the Course Navigator
+    let mut course_maps: Vec<CourseMap> = Vec::with_capacity(course_map_ids.len());
+
+    for chunk in course_map_ids.chunks(threads) {
+        let results = join_all(
+            chunk
+                .iter()
+                .map(|uuid| datastore.get_unrendered_course_map(*uuid)),
+        )
+        .await;
+
+        course_maps.extend(results.into_iter().filter_map(Result::ok));
+    }
+
+    let active: HashSet<_> = active.into_iter().collect();
+
+    let mut indexes = HashMap::with_capacity(courses.len());
+    let mut progress = HashMap::with_capacity(courses.len());
+
+    for (course, course_progress) in courses {
+        if let Some(uuid) = course.uuid {
+            indexes.insert(uuid, course);
+            progress.insert(uuid, course_progress);
         }
+    }
+
+    let recommended =
+        recommendation::recommend(&course_maps, &progress, &active, threshold.clamp(0.0, 1.0));
 
-        let mut results = try_join_all(future_set).await?;
+    let mut courses = Vec::with_capacity(recommended.len());
 
-        course_maps.append(&mut results);
+    for uuid in recommended {
+        if let (Some(course), Some(course_progress)) =
+            (indexes.remove(&uuid), progress.remove(&uuid))
+        {
+            courses.push((course, course_progress));
+        }
     }
 
-    Ok(course_maps)
+    Ok(courses)
+}
+
+/// Indexes the textbooks of the Courses found by the last scan, so that they're included in search results.
+pub(super) async fn update_search_index(state: &State) -> Result<(), ErrorWrapper> {
+    let threads = state.get_threads().await;
+
+    state
+        .get_datastore()
+        .await?
+        .update_search_index(threads)
+        .await
+        .map_err(|e| ErrorWrapper::new("Unable to update search index".to_string(), &e))
+}
+
+/// Searches the textbooks which have been indexed so far.
+pub(super) async fn search(
+    state: &State,
+    query: String,
+    limit: usize,
+) -> Result<Vec<SearchHit>, ErrorWrapper> {
+    state
+        .get_datastore()
+        .await?
+        .search(query, limit)
+        .await
+        .map_err(|e| ErrorWrapper::new("Unable to search textbooks".to_string(), &e))
+}
+
+pub(super) async fn get_annotations(
+    state: &State,
+    id: Uuid,
+) -> Result<(Course, Vec<Annotation>), ErrorWrapper> {
+    let course = state
+        .get_datastore()
+        .await?
+        .get_course(id)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to get Course {id}"), &e))?;
+
+    let annotations = state
+        .get_database()
+        .await?
+        .get_annotations(course.clone())
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to get annotations for Course {id}"), &e))?;
+
+    Ok((course, annotations))
+}
+
+/// Writes a Course's annotations to a Markdown file.
+pub(super) async fn export_annotations(
+    state: &State,
+    id: Uuid,
+    dest: PathBuf,
+) -> Result<(), ErrorWrapper> {
+    let (course, annotations) = get_annotations(state, id).await?;
+
+    let markdown =
+        task::spawn_blocking(move || annotations::to_markdown(&course, &annotations)).await?;
+
+    tokio::fs::write(&dest, markdown)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to export annotations for Course {id}"), &e))
+}
+
+pub(super) async fn get_course_map_progress(
+    state: &State,
+    id: Uuid,
+) -> Result<(String, Option<RenderError>), ErrorWrapper> {
+    let datastore = state.get_datastore().await?;
+    let threads = state.get_threads().await;
+
+    let (course_map, _, _, _) = datastore
+        .get_course_map(id)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to get Course Map {id}"), &e))?;
+
+    let mut ids = Vec::with_capacity(course_map.courses.len());
+
+    for item in course_map.item_ids() {
+        if datastore.has_course(item).await.unwrap_or(false) {
+            ids.push(item);
+        }
+    }
+
+    // Courses which fail to load are already reported by the Course Navigator, and are displayed as incomplete
+    let (courses, _) = get_courses(state, &ids, threads).await?;
+
+    let progress: CourseMapProgress = courses
+        .into_iter()
+        .filter_map(|(course, progress)| Some((course.uuid?, progress.overall_completion()?)))
+        .collect();
+
+    datastore
+        .render_course_map_progress(id, progress)
+        .await
+        .map_err(|e| ErrorWrapper::new(format!("Unable to get Course Map {id}"), &e))
 }
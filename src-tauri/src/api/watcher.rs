@@ -0,0 +1,121 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use notify::{Event, RecursiveMode, Watcher};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::{util::ErrorWrapper, State};
+
+/// The event emitted to the frontend with a ``ResourceChanges`` payload whenever resources change on disk
+pub const RESOURCES_CHANGED_EVENT: &str = "resources-changed";
+
+/// How long to wait after a change for further changes, so that bulk operations (such as importing an archive) only cause a single refresh
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// The maximum time changes can be held back while the resource folder is continuously changing
+const MAX_DEBOUNCE_DELAY: Duration = Duration::from_secs(5);
+
+/// Starts watching the resource folder for changes in the background.
+///
+/// Changed resources are removed from the ``DataStore``'s cache and reported to the frontend. If the resource folder can't be watched, resources are read from disk on every request instead, and the reason is reported alongside the resource listing.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<State>();
+
+        let root = match state.get_datastore().await {
+            Ok(datastore) => datastore.root.clone(),
+            Err(error) => {
+                let _ = state.watch_error.set(ErrorWrapper {
+                    message: "Unable to watch resource folder".to_string(),
+                    cause: error.cause,
+                });
+                return;
+            }
+        };
+
+        let (sender, receiver) = mpsc::channel();
+
+        let watcher = notify::recommended_watcher(sender).and_then(|mut watcher| {
+            watcher.watch(&root, RecursiveMode::Recursive)?;
+            Ok(watcher)
+        });
+
+        let watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                let _ = state.watch_error.set(ErrorWrapper::new(
+                    "Unable to watch resource folder".to_string(),
+                    &error,
+                ));
+                return;
+            }
+        };
+
+        if let Some(datastore) = state.datastore.get() {
+            datastore.enable_tracking();
+        }
+
+        let app = app.clone();
+
+        thread::spawn(move || {
+            // The watcher stops when dropped
+            let _watcher = watcher;
+
+            while let Some((paths, missed)) = next_batch(&receiver) {
+                let state = app.state::<State>();
+
+                let Some(datastore) = state.datastore.get() else {
+                    continue;
+                };
+
+                let changes = if missed {
+                    datastore.invalidate_all()
+                } else {
+                    datastore.invalidate(paths.iter().map(PathBuf::as_path))
+                };
+
+                if !changes.is_empty() {
+                    let _ = app.emit(RESOURCES_CHANGED_EVENT, changes);
+                }
+            }
+        });
+    });
+}
+
+/// Waits for a change to the resource folder, then collects changes until the folder has settled.
+///
+/// Returns the changed paths, and whether any changes may have been missed. Returns ``None`` once the watcher has stopped.
+fn next_batch(receiver: &Receiver<notify::Result<Event>>) -> Option<(Vec<PathBuf>, bool)> {
+    let mut paths = Vec::new();
+    let mut missed = false;
+
+    let mut add = |result: notify::Result<Event>| match result {
+        Ok(event) => {
+            missed |= event.need_rescan();
+
+            // Resources are read frequently, and reads can't affect them
+            if !event.kind.is_access() {
+                paths.extend(event.paths);
+            }
+        }
+        Err(_) => missed = true,
+    };
+
+    add(receiver.recv().ok()?);
+
+    let start = Instant::now();
+
+    while start.elapsed() < MAX_DEBOUNCE_DELAY {
+        match receiver.recv_timeout(DEBOUNCE_DELAY) {
+            Ok(result) => add(result),
+            Err(RecvTimeoutError::Timeout) => break,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+
+    Some((paths, missed))
+}
@@ -0,0 +1,379 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use uuid::{fmt::Simple, Uuid};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::storage::Error;
+
+/// The maximum number of entries (files and folders) allowed in a single archive
+pub(super) const MAX_ENTRIES: usize = 65_536;
+
+/// The maximum total uncompressed size of a single archive, in bytes
+pub(super) const MAX_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+const PARTIAL_EXTENSION: &str = "partial";
+const QUARANTINE_EXTENSION: &str = "quarantined";
+const REPLACED_EXTENSION: &str = "replaced";
+
+/// The file written into folders unpacked from packed textbooks, containing the size and modification time of the textbook they were unpacked from
+const UNPACKED_MARKER: &str = ".unpacked";
+
+/// Creates an archive at `dest` containing each source file or folder under its corresponding archive path.
+///
+/// The archive is written to a temporary file, which is moved to `dest` only if packing succeeds.
+pub(super) fn pack(dest: &Path, sources: &[(PathBuf, String)]) -> Result<(), Error> {
+    let mut temporary = OsString::from(dest.as_os_str());
+    temporary.push(".");
+    temporary.push(PARTIAL_EXTENSION);
+    let temporary = PathBuf::from(temporary);
+
+    let result = (|| -> Result<(), Error> {
+        let mut writer = ZipWriter::new(File::create(&temporary)?);
+
+        for (source, name) in sources {
+            pack_entry(&mut writer, source, name)?;
+        }
+
+        writer.finish()?;
+
+        Ok(())
+    })();
+
+    match result.and_then(|()| Ok(fs::rename(&temporary, dest)?)) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            let _ = fs::remove_file(&temporary);
+
+            Err(error)
+        }
+    }
+}
+
+fn pack_entry(writer: &mut ZipWriter<File>, path: &Path, name: &str) -> Result<(), Error> {
+    let metadata = path.symlink_metadata()?;
+
+    if metadata.is_dir() {
+        writer.add_directory(name, SimpleFileOptions::default())?;
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+
+            pack_entry(
+                writer,
+                &entry.path(),
+                &format!("{name}/{}", entry.file_name().to_string_lossy()),
+            )?;
+        }
+    } else if metadata.is_file() {
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .large_file(metadata.len() >= u64::from(u32::MAX));
+
+        writer.start_file(name, options)?;
+        io::copy(&mut File::open(path)?, writer)?;
+    }
+
+    Ok(())
+}
+
+/// Unpacks a packed textbook into the folder at `dest`, returning whether it was unpacked.
+///
+/// The textbook is kept, so that the Course index can keep referencing it. An existing folder at `dest` is only replaced if it was unpacked from a different version of the textbook, so folders which weren't created by unpacking are never removed.
+pub(super) fn unpack_textbook(path: &Path, dest: &Path) -> Result<bool, Error> {
+    let stamp = textbook_stamp(path)?;
+
+    if dest.exists() {
+        match fs::read_to_string(dest.join(UNPACKED_MARKER)) {
+            Ok(existing) if existing != stamp => {}
+            _ => return Ok(false),
+        }
+    }
+
+    let parent = dest.parent().unwrap_or(dest);
+    let temporary = extract_temporary(path, parent)?;
+
+    let result = (|| -> Result<(), Error> {
+        fs::write(temporary.join(UNPACKED_MARKER), &stamp)?;
+
+        // The previous version is only removed once the new version is in place
+        let previous = if dest.exists() {
+            let previous = replaced_path(parent);
+            fs::rename(dest, &previous)?;
+
+            Some(previous)
+        } else {
+            None
+        };
+
+        if let Err(error) = fs::rename(&temporary, dest) {
+            if let Some(previous) = &previous {
+                let _ = fs::rename(previous, dest);
+            }
+
+            return Err(error.into());
+        }
+
+        if let Some(previous) = previous {
+            let _ = fs::remove_dir_all(previous);
+        }
+
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&temporary);
+    }
+
+    result.map(|()| true)
+}
+
+/// Identifies the version of a packed textbook using its size and modification time.
+fn textbook_stamp(path: &Path) -> Result<String, Error> {
+    let metadata = path.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Ok(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+/// Deletes an archive if the result of importing it was successful, and quarantines it otherwise.
+pub(super) fn finish(path: &Path, result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Ok(()) => {
+            fs::remove_file(path)?;
+
+            Ok(())
+        }
+        Err(error) => {
+            let mut quarantined = OsString::from(path.as_os_str());
+            quarantined.push(".");
+            quarantined.push(QUARANTINE_EXTENSION);
+            let quarantined = PathBuf::from(quarantined);
+
+            if fs::rename(path, &quarantined).is_err() {
+                return Err(error);
+            }
+
+            Err(Error::Quarantined {
+                path: quarantined,
+                source: Box::new(error),
+            })
+        }
+    }
+}
+
+/// Returns a new hidden path within `parent`, which is ignored when scanning the resource folder due to its extension.
+fn hidden_path(parent: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(Simple::from_uuid(Uuid::new_v4()).encode_lower(&mut Uuid::encode_buffer()));
+    name.push(".");
+    name.push(extension);
+
+    parent.join(name)
+}
+
+/// Returns a new hidden path within `parent`, where a resource can be kept while it's being replaced.
+pub(super) fn replaced_path(parent: &Path) -> PathBuf {
+    hidden_path(parent, REPLACED_EXTENSION)
+}
+
+/// Extracts an archive into a new hidden folder within `parent`, which is removed if extraction fails.
+pub(super) fn extract_temporary(path: &Path, parent: &Path) -> Result<PathBuf, Error> {
+    let temporary = hidden_path(parent, PARTIAL_EXTENSION);
+
+    fs::create_dir(&temporary)?;
+
+    match extract(path, &temporary) {
+        Ok(()) => Ok(temporary),
+        Err(error) => {
+            let _ = fs::remove_dir_all(&temporary);
+
+            Err(error)
+        }
+    }
+}
+
+fn extract(path: &Path, dest: &Path) -> Result<(), Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+
+    if archive.len() > MAX_ENTRIES {
+        return Err(Error::ArchiveTooManyEntries);
+    }
+
+    // Declared sizes can't be trusted, but checking them first avoids extracting most of an oversized archive
+    let mut declared_size: u64 = 0;
+
+    for index in 0..archive.len() {
+        declared_size = declared_size.saturating_add(archive.by_index_raw(index)?.size());
+    }
+
+    if declared_size > MAX_SIZE {
+        return Err(Error::ArchiveTooLarge);
+    }
+
+    let mut remaining = MAX_SIZE;
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+
+        let Some(relative_path) = entry.enclosed_name() else {
+            return Err(Error::ArchiveEntryOutsideRoot(entry.name().to_string()));
+        };
+
+        if entry.is_symlink() {
+            return Err(Error::ArchiveSymlink(entry.name().to_string()));
+        }
+
+        let entry_path = dest.join(relative_path);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&entry_path)?;
+            continue;
+        }
+
+        if let Some(parent) = entry_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut output = File::create(&entry_path)?;
+        let written = io::copy(&mut (&mut entry).take(remaining + 1), &mut output)?;
+
+        if written > remaining {
+            return Err(Error::ArchiveTooLarge);
+        }
+
+        remaining -= written;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use zip::{write::SimpleFileOptions, CompressionMethod};
+
+    use super::{
+        super::testing::{write_archive, TemporaryFolder},
+        extract_temporary, Error, MAX_ENTRIES,
+    };
+
+    fn stored() -> SimpleFileOptions {
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+    }
+
+    /// Extracts an archive within `folder`, checking that nothing is left behind if extraction fails.
+    fn extract(folder: &Path, archive: &Path) -> Result<PathBuf, Error> {
+        let result = extract_temporary(archive, folder);
+
+        if result.is_err() {
+            assert_eq!(fs::read_dir(folder).unwrap().count(), 1);
+        }
+
+        result
+    }
+
+    #[test]
+    fn extracts_archive() {
+        let folder = TemporaryFolder::new();
+        let archive = write_archive(&folder, "archive.zip", |writer| {
+            writer.add_directory("course", stored()).unwrap();
+            writer.start_file("course/course.toml", stored()).unwrap();
+            writer.write_all(b"title = \"Course\"").unwrap();
+        });
+
+        let extracted = extract(&folder, &archive).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(extracted.join("course").join("course.toml")).unwrap(),
+            "title = \"Course\""
+        );
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        let folder = TemporaryFolder::new();
+        let archive = write_archive(&folder, "archive.zip", |writer| {
+            writer.start_file("../outside.toml", stored()).unwrap();
+            writer.write_all(b"title = \"Outside\"").unwrap();
+        });
+
+        assert!(matches!(
+            extract(&folder, &archive),
+            Err(Error::ArchiveEntryOutsideRoot(name)) if name == "../outside.toml"
+        ));
+        assert!(!folder.join("outside.toml").exists());
+    }
+
+    #[test]
+    fn rejects_symlinks() {
+        let folder = TemporaryFolder::new();
+        let archive = write_archive(&folder, "archive.zip", |writer| {
+            writer.add_symlink("link", "/etc/passwd", stored()).unwrap();
+        });
+
+        assert!(matches!(
+            extract(&folder, &archive),
+            Err(Error::ArchiveSymlink(name)) if name == "link"
+        ));
+    }
+
+    #[test]
+    fn rejects_too_many_entries() {
+        let folder = TemporaryFolder::new();
+        let archive = write_archive(&folder, "archive.zip", |writer| {
+            for index in 0..=MAX_ENTRIES {
+                writer.add_directory(format!("{index}"), stored()).unwrap();
+            }
+        });
+
+        assert!(matches!(
+            extract(&folder, &archive),
+            Err(Error::ArchiveTooManyEntries)
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_entries() {
+        let folder = TemporaryFolder::new();
+        let archive = write_archive(&folder, "archive.zip", |writer| {
+            for name in ["a", "b"] {
+                writer.start_file(name, stored()).unwrap();
+                writer.write_all(b"a").unwrap();
+            }
+        });
+
+        // Declare a size of almost 4GiB for each entry within the central directory, without writing that much data
+        let mut data = fs::read(&archive).unwrap();
+        let mut offset = 0;
+
+        while let Some(position) = data[offset..]
+            .windows(4)
+            .position(|window| window == b"PK\x01\x02")
+        {
+            let header = offset + position;
+            data[header + 24..header + 28].copy_from_slice(&0xFFFF_FFF0_u32.to_le_bytes());
+            offset = header + 4;
+        }
+
+        fs::write(&archive, data).unwrap();
+
+        assert!(matches!(
+            extract(&folder, &archive),
+            Err(Error::ArchiveTooLarge)
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::{fmt::Simple, Uuid};
+
+use super::svg::RENDERER_VERSION;
+
+/// An on-disk cache of rendered Course Maps, keyed by a hash of the Course Map's source and the renderer version
+///
+/// Only the latest rendering of each Course Map is kept, along with the latest rendering of each variant (such as renderings displaying the learner's progress). Variants are removed whenever the Course Map is rendered again, as they're based on the same source.
+///
+/// Renderings which failed are cached as well, so that a Course Map which can't be laid out isn't laid out again until it changes. This matters because a timed out layout keeps running in the background, so retrying it on every request would start another thread each time.
+pub(super) struct RenderCache {
+    root: PathBuf,
+}
+
+impl RenderCache {
+    pub(super) fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+    /// Returns the cached rendering of a Course Map, rendering it if it isn't available.
+    ///
+    /// Failing to access the cache never prevents rendering.
+    pub(super) fn get_or_render<E: Serialize + DeserializeOwned>(
+        &self,
+        id: Uuid,
+        variant: Option<&str>,
+        source: &str,
+        render: impl FnOnce() -> Result<String, E>,
+    ) -> Result<String, E> {
+        let mut prefix = Simple::from_uuid(id)
+            .encode_lower(&mut Uuid::encode_buffer())
+            .to_string();
+
+        if let Some(variant) = variant {
+            prefix.push('-');
+            prefix.push_str(variant);
+        }
+
+        let hash = hash_source(source);
+        let path = self.root.join(format!("{prefix}-{hash}.svg"));
+        let error_path = self.root.join(format!("{prefix}-{hash}.error.json"));
+
+        if let Ok(rendered) = fs::read_to_string(&path) {
+            return Ok(rendered);
+        }
+
+        if let Some(error) = fs::read(&error_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+        {
+            return Err(error);
+        }
+
+        match render() {
+            Ok(rendered) => {
+                let _ = self.store(&prefix, &path, rendered.as_bytes());
+
+                Ok(rendered)
+            }
+            Err(error) => {
+                if let Ok(data) = serde_json::to_vec(&error) {
+                    let _ = self.store(&prefix, &error_path, &data);
+                }
+
+                Err(error)
+            }
+        }
+    }
+    fn store(&self, prefix: &str, path: &Path, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+
+        let prefix = format!("{prefix}-");
+
+        // Renderings of previous versions of the Course Map will never be used again
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let filename = entry.file_name();
+
+            if filename.to_string_lossy().starts_with(&prefix) && entry.path() != path {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+
+        let temporary = self.root.join(format!(
+            ".{}.partial",
+            Simple::from_uuid(Uuid::new_v4()).encode_lower(&mut Uuid::encode_buffer())
+        ));
+
+        fs::write(&temporary, data)?;
+
+        fs::rename(&temporary, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })
+    }
+}
+
+fn hash_source(source: &str) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(RENDERER_VERSION.to_le_bytes());
+    hasher.update(source.as_bytes());
+
+    format!("{:x}", hasher.finalize())
+}
@@ -0,0 +1,425 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use roxmltree::{Document, Node, ParsingOptions};
+use thiserror::Error;
+
+const OPS_NAMESPACE: &str = "http://www.idpf.org/2007/ops";
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parsing(#[from] roxmltree::Error),
+    #[error("EPUB container does not specify a package document")]
+    MissingPackage,
+    #[error("EPUB package does not contain a Table of Contents")]
+    MissingToc,
+}
+
+/// The parsed package document of an unpacked EPUB
+#[derive(Debug)]
+pub struct Package {
+    /// The folder containing the package document, which all hrefs are relative to
+    pub root: PathBuf,
+    /// The title of the publication, if one is specified
+    pub title: Option<String>,
+    /// The hrefs of all content documents in reading order
+    pub spine: Vec<String>,
+    /// The hierarchical Table of Contents, read from the EPUB 3 navigation document or the EPUB 2 NCX
+    pub toc: Vec<TocEntry>,
+}
+
+/// An entry within an EPUB's Table of Contents
+#[derive(Debug)]
+pub struct TocEntry {
+    pub label: String,
+    /// The entry's href, resolved relative to the package document
+    ///
+    /// This matches the section identifiers used by the Textbook Viewer.
+    pub href: Option<String>,
+    pub children: Vec<TocEntry>,
+}
+
+impl TocEntry {
+    /// Returns all entries in the Table of Contents in depth-first order.
+    pub fn flatten(entries: &[TocEntry]) -> Vec<&TocEntry> {
+        let mut flattened = Vec::new();
+
+        for entry in entries {
+            flattened.push(entry);
+            flattened.append(&mut Self::flatten(&entry.children));
+        }
+
+        flattened
+    }
+}
+
+struct ManifestItem {
+    href: String,
+    media_type: String,
+    properties: String,
+}
+
+impl Package {
+    /// Reads the package document and Table of Contents of an unpacked EPUB.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let container = read_document(&path.join("META-INF").join("container.xml"))?;
+        let container = parse_document(&container)?;
+
+        let package_path = container
+            .descendants()
+            .filter(|n| n.has_tag_name("rootfile"))
+            .find(|n| {
+                n.attribute("media-type")
+                    .is_none_or(|t| t == "application/oebps-package+xml")
+            })
+            .and_then(|n| n.attribute("full-path"))
+            .ok_or(Error::MissingPackage)?;
+        let package_path = path.join(decode_href(package_path));
+
+        let root = package_path
+            .parent()
+            .map_or_else(|| path.to_path_buf(), Path::to_path_buf);
+
+        let package = read_document(&package_path)?;
+        let package = parse_document(&package)?;
+
+        let title = package
+            .descendants()
+            .find(|n| n.has_tag_name("title"))
+            .map(node_text)
+            .filter(|t| !t.is_empty());
+
+        let mut manifest = HashMap::new();
+
+        for item in package.descendants().filter(|n| n.has_tag_name("item")) {
+            if let (Some(id), Some(href)) = (item.attribute("id"), item.attribute("href")) {
+                manifest.insert(
+                    id,
+                    ManifestItem {
+                        href: resolve_href("", href),
+                        media_type: item.attribute("media-type").unwrap_or_default().to_string(),
+                        properties: item.attribute("properties").unwrap_or_default().to_string(),
+                    },
+                );
+            }
+        }
+
+        let spine_node = package.descendants().find(|n| n.has_tag_name("spine"));
+
+        let spine = spine_node
+            .iter()
+            .flat_map(Node::children)
+            .filter(|n| n.has_tag_name("itemref"))
+            .filter_map(|n| n.attribute("idref"))
+            .filter_map(|id| manifest.get(id))
+            .map(|item| item.href.clone())
+            .collect();
+
+        let nav = manifest
+            .values()
+            .find(|item| item.properties.split_whitespace().any(|p| p == "nav"));
+        let ncx = spine_node
+            .and_then(|n| n.attribute("toc"))
+            .and_then(|id| manifest.get(id))
+            .or_else(|| {
+                manifest
+                    .values()
+                    .find(|item| item.media_type == "application/x-dtbncx+xml")
+            });
+
+        // Matches epub.js, which prefers the navigation document over the NCX
+        let toc = if let Some(nav) = nav {
+            let document = read_document(&root.join(decode_href(&nav.href)))?;
+            let document = parse_document(&document)?;
+
+            parse_nav(&document, &nav.href).ok_or(Error::MissingToc)?
+        } else if let Some(ncx) = ncx {
+            let document = read_document(&root.join(decode_href(&ncx.href)))?;
+            let document = parse_document(&document)?;
+
+            parse_ncx(&document, &ncx.href).ok_or(Error::MissingToc)?
+        } else {
+            return Err(Error::MissingToc);
+        };
+
+        Ok(Package {
+            root,
+            title,
+            spine,
+            toc,
+        })
+    }
+}
+
+fn parse_nav(document: &Document, base: &str) -> Option<Vec<TocEntry>> {
+    let navs: Vec<_> = document
+        .descendants()
+        .filter(|n| n.has_tag_name("nav"))
+        .collect();
+
+    let nav = navs
+        .iter()
+        .find(|n| {
+            n.attribute((OPS_NAMESPACE, "type"))
+                .is_some_and(|t| t.split_whitespace().any(|t| t == "toc"))
+        })
+        .or(navs.first())?;
+
+    let list = nav.children().find(|n| n.has_tag_name("ol"))?;
+
+    Some(parse_nav_list(list, base))
+}
+
+fn parse_nav_list(list: Node, base: &str) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+
+    for item in list.children().filter(|n| n.has_tag_name("li")) {
+        let link = item
+            .children()
+            .find(|n| n.has_tag_name("a") || n.has_tag_name("span"));
+
+        let label = link.map(node_text).unwrap_or_default();
+        let href = link
+            .and_then(|n| n.attribute("href"))
+            .map(|href| resolve_href(base, href));
+
+        let children = item
+            .children()
+            .find(|n| n.has_tag_name("ol"))
+            .map(|n| parse_nav_list(n, base))
+            .unwrap_or_default();
+
+        entries.push(TocEntry {
+            label,
+            href,
+            children,
+        });
+    }
+
+    entries
+}
+
+fn parse_ncx(document: &Document, base: &str) -> Option<Vec<TocEntry>> {
+    let map = document.descendants().find(|n| n.has_tag_name("navMap"))?;
+
+    Some(parse_ncx_points(map, base))
+}
+
+fn parse_ncx_points(parent: Node, base: &str) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+
+    for point in parent.children().filter(|n| n.has_tag_name("navPoint")) {
+        let label = point
+            .children()
+            .find(|n| n.has_tag_name("navLabel"))
+            .map(node_text)
+            .unwrap_or_default();
+        let href = point
+            .children()
+            .find(|n| n.has_tag_name("content"))
+            .and_then(|n| n.attribute("src"))
+            .map(|href| resolve_href(base, href));
+
+        entries.push(TocEntry {
+            label,
+            href,
+            children: parse_ncx_points(point, base),
+        });
+    }
+
+    entries
+}
+
+fn node_text(node: Node) -> String {
+    let mut text = String::new();
+
+    for descendant in node.descendants().filter(Node::is_text) {
+        text.push_str(descendant.text().unwrap_or_default());
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Elements which separate words, even when no whitespace is present between them
+const BLOCK_ELEMENTS: [&str; 24] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "li",
+    "p",
+    "pre",
+    "section",
+    "td",
+];
+
+/// Extracts the readable text of an XHTML content document, with whitespace normalized.
+///
+/// Only the document's body is included, excluding scripts and stylesheets.
+pub(super) fn extract_text(path: &Path) -> Result<String, Error> {
+    let document = read_document(path)?;
+    let document = parse_document(&document)?;
+
+    let body = document
+        .descendants()
+        .find(|n| n.has_tag_name("body"))
+        .unwrap_or_else(|| document.root_element());
+
+    let mut text = String::new();
+    collect_text(body, &mut text);
+
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn collect_text(node: Node, text: &mut String) {
+    for child in node.children() {
+        if child.is_text() {
+            text.push_str(child.text().unwrap_or_default());
+        } else if child.is_element() {
+            let name = child.tag_name().name();
+
+            if name == "script" || name == "style" {
+                continue;
+            }
+
+            let block = BLOCK_ELEMENTS.contains(&name);
+
+            if block {
+                text.push(' ');
+            }
+
+            collect_text(child, text);
+
+            if block {
+                text.push(' ');
+            }
+        }
+    }
+}
+
+/// Reads an XML document from disk, replacing HTML-only named entities which would otherwise cause parsing to fail.
+pub(super) fn read_document(path: &Path) -> Result<String, Error> {
+    let mut text = fs::read_to_string(path)?;
+
+    if text.contains('&') {
+        for (entity, replacement) in [
+            ("&nbsp;", "&#160;"),
+            ("&ndash;", "&#8211;"),
+            ("&mdash;", "&#8212;"),
+            ("&lsquo;", "&#8216;"),
+            ("&rsquo;", "&#8217;"),
+            ("&ldquo;", "&#8220;"),
+            ("&rdquo;", "&#8221;"),
+            ("&hellip;", "&#8230;"),
+            ("&copy;", "&#169;"),
+        ] {
+            text = text.replace(entity, replacement);
+        }
+    }
+
+    Ok(text)
+}
+
+pub(super) fn parse_document(text: &str) -> Result<Document<'_>, Error> {
+    Ok(Document::parse_with_options(
+        text,
+        ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        },
+    )?)
+}
+
+/// Resolves an href relative to the document at `base`, where both are relative to the package document.
+///
+/// This mirrors how the Textbook Viewer generates section identifiers.
+pub(super) fn resolve_href(base: &str, href: &str) -> String {
+    if href.contains("://") {
+        return href.to_string();
+    }
+
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+
+    let mut segments: Vec<&str> = if path.is_empty() {
+        base.split('/').collect()
+    } else {
+        let mut segments: Vec<&str> = base.split('/').collect();
+        segments.pop();
+        segments.extend(path.split('/'));
+        segments
+    };
+
+    let mut resolved = Vec::with_capacity(segments.len());
+
+    for segment in segments.drain(..) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                resolved.pop();
+            }
+            segment => resolved.push(segment),
+        }
+    }
+
+    let mut resolved = resolved.join("/");
+
+    if let Some(fragment) = fragment {
+        resolved.push('#');
+        resolved.push_str(fragment);
+    }
+
+    resolved
+}
+
+/// Converts an href into a relative file path, removing its fragment and decoding percent-encoded characters.
+pub(super) fn decode_href(href: &str) -> PathBuf {
+    let path = href.split('#').next().unwrap_or_default().as_bytes();
+
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut index = 0;
+
+    while index < path.len() {
+        if path[index] == b'%' && index + 2 < path.len() {
+            if let Ok(byte) = u8::from_str_radix(
+                std::str::from_utf8(&path[index + 1..index + 3]).unwrap_or_default(),
+                16,
+            ) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+
+        decoded.push(path[index]);
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded)
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != "." && *s != "..")
+        .collect()
+}
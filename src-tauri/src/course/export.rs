@@ -0,0 +1,275 @@
+use std::{collections::HashMap, fmt::Write};
+
+use resvg::{
+    tiny_skia::{Color, Pixmap, Transform},
+    usvg::{self, Options, Tree},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::{fmt::Simple, Uuid};
+
+use super::{CourseMap, CourseMapItem, CourseMapOrientation, CourseMapRelationType};
+
+/// The scale at which Course Maps are rasterized, relative to their SVG size
+pub const PNG_SCALE: f32 = 2.0;
+
+/// Formats which a Course Map can be exported to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A Graphviz graph
+    Dot,
+    /// A Mermaid flowchart
+    Mermaid,
+    /// A self-contained SVG image
+    Svg,
+    /// A PNG image, rasterized from the self-contained SVG image
+    Png,
+}
+
+impl ExportFormat {
+    /// Parses the name of a format, which is also its file extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "dot" | "gv" => Some(Self::Dot),
+            "mermaid" | "mmd" => Some(Self::Mermaid),
+            "svg" => Some(Self::Svg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error(transparent)]
+    InvalidSvg(#[from] usvg::Error),
+    #[error("Course Map is too large to be rasterized")]
+    TooLarge,
+    #[error("Unable to encode PNG: {0}")]
+    Encoding(String),
+}
+
+impl CourseMap {
+    /// Creates a Graphviz (DOT) representation of a ``CourseMap``.
+    ///
+    /// Prerequisites are drawn with arrows, Corequisites without, optional relations are dashed, and Layout relations are invisible. Relation labels and weights are included as edge labels. Nested Course Maps which are displayed Inline are replaced by their items, while other nested Course Maps are represented by a single node.
+    pub fn generate_dot(&self) -> String {
+        let items = self.expand_items();
+
+        let mut output = String::new();
+
+        let _ = writeln!(output, "digraph \"{}\" {{", escape_dot(&self.title));
+        let _ = writeln!(
+            output,
+            "\trankdir={};",
+            match self.orientation {
+                CourseMapOrientation::TopToBottom => "TB",
+                CourseMapOrientation::LeftToRight => "LR",
+            }
+        );
+        output.push_str("\tnode [shape=box, style=\"rounded,filled\", fillcolor=white];\n");
+
+        for item in &items {
+            let _ = writeln!(
+                output,
+                "\t\"{}\" [label=\"{}\", color=\"{}\"];",
+                item.uuid,
+                escape_dot(&item.label),
+                escape_dot(&item.color)
+            );
+        }
+
+        let colors = item_colors(&items);
+
+        for item in &items {
+            for relation in &item.relations {
+                if !colors.contains_key(&relation.uuid) {
+                    continue;
+                }
+
+                let (arrowhead, style) = match relation.r#type {
+                    CourseMapRelationType::Prerequisite => ("normal", "solid"),
+                    CourseMapRelationType::Corequisite => ("none", "solid"),
+                    CourseMapRelationType::Layout => ("none", "invis"),
+                };
+
+                let style = if relation.optional && style == "solid" {
+                    "dashed"
+                } else {
+                    style
+                };
+
+                let _ = writeln!(
+                    output,
+                    "\t\"{}\" -> \"{}\" [class=\"{}\", label=\"{}\", arrowhead={arrowhead}, style={style}, color=\"{}\"];",
+                    relation.uuid,
+                    item.uuid,
+                    relation_class(relation.r#type),
+                    escape_dot(&relation.caption()),
+                    escape_dot(colors[&relation.uuid]),
+                );
+            }
+        }
+
+        output.push_str("}\n");
+
+        output
+    }
+    /// Creates a Mermaid flowchart representation of a ``CourseMap``.
+    ///
+    /// Relations are represented in the same way as ``CourseMap::generate_dot``.
+    pub fn generate_mermaid(&self) -> String {
+        let items = self.expand_items();
+
+        let mut output = String::new();
+
+        let _ = writeln!(
+            output,
+            "---\ntitle: \"{}\"\n---",
+            escape_mermaid(&self.title)
+        );
+        let _ = writeln!(
+            output,
+            "flowchart {}",
+            match self.orientation {
+                CourseMapOrientation::TopToBottom => "TB",
+                CourseMapOrientation::LeftToRight => "LR",
+            }
+        );
+
+        for item in &items {
+            let _ = writeln!(
+                output,
+                "\t{}[\"{}\"]",
+                mermaid_id(item.uuid),
+                escape_mermaid(&item.label)
+            );
+            let _ = writeln!(
+                output,
+                "\tstyle {} stroke:{},fill:white",
+                mermaid_id(item.uuid),
+                sanitize_mermaid_color(&item.color)
+            );
+        }
+
+        let colors = item_colors(&items);
+
+        // Mermaid styles links by the order in which they're defined
+        let mut link_styles = Vec::new();
+
+        for item in &items {
+            for relation in &item.relations {
+                if !colors.contains_key(&relation.uuid) {
+                    continue;
+                }
+
+                let link = match (relation.r#type, relation.optional) {
+                    (CourseMapRelationType::Prerequisite, false) => "-->",
+                    (CourseMapRelationType::Prerequisite, true) => "-.->",
+                    (CourseMapRelationType::Corequisite, false) => "---",
+                    (CourseMapRelationType::Corequisite, true) => "-.-",
+                    (CourseMapRelationType::Layout, _) => "~~~",
+                };
+
+                let caption = relation.caption();
+
+                // Invisible links can't have a label
+                let label =
+                    if caption.is_empty() || relation.r#type == CourseMapRelationType::Layout {
+                        String::new()
+                    } else {
+                        format!("|\"{}\"|", escape_mermaid(&caption))
+                    };
+
+                let _ = writeln!(
+                    output,
+                    "\t{} {link}{label} {}",
+                    mermaid_id(relation.uuid),
+                    mermaid_id(item.uuid)
+                );
+
+                // Layout relations are invisible, so they aren't styled
+                link_styles.push(
+                    (relation.r#type != CourseMapRelationType::Layout)
+                        .then(|| sanitize_mermaid_color(colors[&relation.uuid])),
+                );
+            }
+        }
+
+        for (index, color) in link_styles.into_iter().enumerate() {
+            if let Some(color) = color {
+                let _ = writeln!(output, "\tlinkStyle {index} stroke:{color}");
+            }
+        }
+
+        output
+    }
+}
+
+/// Rasterizes an SVG into a PNG image with a white background, at `scale` times its original size.
+///
+/// Text is drawn using the fonts installed on the system.
+pub fn rasterize_svg(svg: &str, scale: f32) -> Result<Vec<u8>, ExportError> {
+    let mut options = Options::default();
+    options.fontdb_mut().load_system_fonts();
+
+    let tree = Tree::from_str(svg, &options)?;
+
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or(ExportError::TooLarge)?;
+
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or(ExportError::TooLarge)?;
+    pixmap.fill(Color::WHITE);
+
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    pixmap
+        .encode_png()
+        .map_err(|e| ExportError::Encoding(e.to_string()))
+}
+
+/// Maps each item to its color, which is used for relations where it is the source.
+fn item_colors(items: &[CourseMapItem]) -> HashMap<Uuid, &str> {
+    items
+        .iter()
+        .map(|item| (item.uuid, item.color.as_str()))
+        .collect()
+}
+
+fn relation_class(relation_type: CourseMapRelationType) -> &'static str {
+    match relation_type {
+        CourseMapRelationType::Prerequisite => "prerequisite",
+        CourseMapRelationType::Corequisite => "corequisite",
+        CourseMapRelationType::Layout => "layout",
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+fn mermaid_id(uuid: Uuid) -> String {
+    format!(
+        "item{}",
+        Simple::from_uuid(uuid).encode_lower(&mut Uuid::encode_buffer())
+    )
+}
+
+/// Removes characters which would end a Mermaid style definition.
+fn sanitize_mermaid_color(color: &str) -> String {
+    color
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '#')
+        .collect()
+}
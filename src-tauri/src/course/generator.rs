@@ -0,0 +1,88 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    epub::{self, Package, TocEntry},
+    Chapter, Course, SectionGroup, Textbook,
+};
+
+impl Textbook {
+    /// Generates a textbook from the Table of Contents of an unpacked EPUB.
+    ///
+    /// Each top-level Table of Contents entry becomes a chapter, with all of its nested entries placed into a single section group. The textbook is referenced by its folder name.
+    pub fn generate(path: &Path) -> Result<Self, epub::Error> {
+        let package = Package::open(path)?;
+
+        let mut chapters = Vec::with_capacity(package.toc.len());
+
+        for entry in &package.toc {
+            let mut sections = Vec::new();
+
+            for child in TocEntry::flatten(&entry.children) {
+                if let Some(href) = &child.href {
+                    if Some(href) != entry.href.as_ref() && !sections.contains(href) {
+                        sections.push(href.clone());
+                    }
+                }
+            }
+
+            if entry.href.is_none() && sections.is_empty() {
+                continue;
+            }
+
+            let groups = if sections.is_empty() {
+                Vec::new()
+            } else {
+                vec![SectionGroup {
+                    weight: super::default_weight(),
+                    sections,
+                }]
+            };
+
+            chapters.push(Chapter {
+                root: entry.href.clone(),
+                groups,
+            });
+        }
+
+        let file = PathBuf::from(path.file_name().unwrap_or_default());
+
+        let mut id = file.to_string_lossy().to_string();
+
+        if !super::is_valid_textbook_id(&id) {
+            id.insert_str(0, "textbook-");
+        }
+
+        Ok(Textbook {
+            id: Some(id),
+            label: package
+                .title
+                .unwrap_or_else(|| file.to_string_lossy().to_string()),
+            file,
+            chapters,
+        })
+    }
+}
+
+impl Course {
+    /// Generates a Course from one or more unpacked EPUBs, which can then be serialized into a Course index.
+    ///
+    /// The Course index should be placed in the folder containing the textbooks.
+    pub fn generate(paths: &[PathBuf]) -> Result<Self, epub::Error> {
+        let mut books = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            books.push(Textbook::generate(path)?);
+        }
+
+        Ok(Course {
+            uuid: None,
+            title: books.first().map(|b| b.label.clone()).unwrap_or_default(),
+            description: None,
+            books,
+            migrations: BTreeMap::new(),
+        })
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{storage::RenderedCourseMap, Course};
+
+/// An in-memory cache of parsed resources, which is kept up to date by reporting filesystem changes to ``DataStore::invalidate``
+///
+/// The cache is only used while tracking is enabled, as changes can't be detected otherwise.
+#[derive(Default)]
+pub(super) struct ResourceIndex {
+    pub(super) tracking: bool,
+    /// Incremented whenever anything is invalidated, so that results read before an invalidation aren't cached after it
+    pub(super) generation: u64,
+    /// The Course and Course Map UUIDs found by the last full scan
+    pub(super) listing: Option<(Vec<Uuid>, Vec<Uuid>)>,
+    pub(super) courses: HashMap<Uuid, Course>,
+    pub(super) course_maps: HashMap<Uuid, RenderedCourseMap>,
+    /// The Courses found by the last full scan, if the search index hasn't been updated with them yet
+    pub(super) unsearched_listing: Option<Vec<Uuid>>,
+    /// Courses which have changed since the search index was last updated
+    ///
+    /// Updating the index with a full listing checks every Course for changes, so this is only used when there isn't one.
+    pub(super) unsearched: HashSet<Uuid>,
+}
+
+impl ResourceIndex {
+    pub(super) fn clear(&mut self) {
+        self.generation += 1;
+        self.listing = None;
+        self.courses.clear();
+        self.course_maps.clear();
+    }
+    pub(super) fn apply(&mut self, changes: &ResourceChanges) {
+        self.generation += 1;
+
+        if changes.listing {
+            self.listing = None;
+        }
+
+        for uuid in &changes.courses {
+            self.courses.remove(uuid);
+        }
+
+        self.unsearched.extend(&changes.courses);
+
+        // Course Maps can include each other, so a change to one may affect the others
+        if !changes.course_maps.is_empty() {
+            self.course_maps.clear();
+        }
+    }
+}
+
+/// The resources affected by a set of filesystem changes
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ResourceChanges {
+    pub courses: Vec<Uuid>,
+    pub course_maps: Vec<Uuid>,
+    /// Resources may have been added, removed, or imported, so the resource folder must be rescanned
+    pub listing: bool,
+}
+
+impl ResourceChanges {
+    /// Determines which resources are affected by changes to the given paths within the resource folder.
+    pub(super) fn from_paths<'a>(root: &Path, paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut changes = Self::default();
+
+        let mut courses = HashSet::new();
+        let mut course_maps = HashSet::new();
+
+        for path in paths {
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+
+            let mut components = relative.components();
+
+            let Some(entry) = components.next() else {
+                changes.listing = true;
+                continue;
+            };
+            let entry = Path::new(entry.as_os_str());
+            let nested = components.next().is_some();
+
+            // Hidden entries are temporary folders created while importing archives
+            if entry.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            // Top-level entries only change when resources are added, removed, or imported, and packed textbooks need to be unpacked
+            if !nested
+                || path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+            {
+                changes.listing = true;
+            }
+
+            let Some(uuid) = entry
+                .file_stem()
+                .and_then(|stem| Uuid::try_parse(&stem.to_string_lossy()).ok())
+            else {
+                continue;
+            };
+
+            match entry.extension() {
+                None => {
+                    courses.insert(uuid);
+                }
+                Some(extension) if !nested && extension.eq_ignore_ascii_case("toml") => {
+                    course_maps.insert(uuid);
+                }
+                Some(_) => {}
+            }
+        }
+
+        changes.courses = courses.into_iter().collect();
+        changes.course_maps = course_maps.into_iter().collect();
+
+        changes
+    }
+    pub fn is_empty(&self) -> bool {
+        !self.listing && self.courses.is_empty() && self.course_maps.is_empty()
+    }
+}
@@ -0,0 +1,460 @@
+//! A simple layered graph layout, used when layout-rs can't lay out a Course Map cleanly
+//!
+//! Nodes are assigned to ranks based on their relations, reordered within each rank to reduce crossing relations, and then positioned as close as possible to the nodes they are related to.
+
+use std::collections::HashMap;
+
+use layout::core::geometry::Point;
+
+use super::CourseMapOrientation;
+
+/// The space between ranks
+const RANK_GAP: f64 = 64.;
+/// The space between nodes within a rank
+const NODE_GAP: f64 = 32.;
+/// The space around the graph
+const MARGIN: f64 = 8.;
+/// The number of times nodes are reordered within ranks
+const ORDERING_PASSES: usize = 16;
+/// The number of times nodes are moved towards the nodes they are related to
+const POSITIONING_PASSES: usize = 4;
+
+/// The result of laying out a graph
+pub(super) struct Layout {
+    /// The center of each node
+    pub nodes: Vec<Point>,
+    /// The points which each edge passes through, starting at the boundary of its source node and ending at the boundary of its destination node
+    ///
+    /// Edges from a node to itself are skipped, so callers must leave them out to keep this in step with their own edges.
+    pub edges: Vec<Vec<Point>>,
+}
+
+/// The nodes of a graph, including the (invisible) nodes which are added where edges pass through a rank
+struct Graph {
+    /// The size of each node along the cross axis (perpendicular to the direction of edges)
+    width: Vec<f64>,
+    rank: Vec<usize>,
+    /// Maps each node to the nodes it has edges to
+    successors: Vec<Vec<usize>>,
+    /// Maps each node to the nodes it has edges from
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn add_node(&mut self, width: f64, rank: usize) -> usize {
+        self.width.push(width);
+        self.rank.push(rank);
+        self.successors.push(Vec::new());
+        self.predecessors.push(Vec::new());
+
+        self.width.len() - 1
+    }
+    fn add_edge(&mut self, source: usize, dest: usize) {
+        self.successors[source].push(dest);
+        self.predecessors[dest].push(source);
+    }
+}
+
+/// Lays out a graph of nodes with the given sizes, where each edge is a (source, destination) pair of node indexes.
+///
+/// Edges which would form a cycle are drawn in the reverse direction of the others.
+#[allow(clippy::cast_precision_loss)]
+pub(super) fn layout(
+    sizes: &[Point],
+    edges: &[(usize, usize)],
+    orientation: CourseMapOrientation,
+) -> Layout {
+    // Sizes along the (cross axis, main axis) depending on the orientation
+    let sizes: Vec<_> = sizes
+        .iter()
+        .map(|size| match orientation {
+            CourseMapOrientation::TopToBottom => *size,
+            CourseMapOrientation::LeftToRight => Point::new(size.y, size.x),
+        })
+        .collect();
+
+    let edges: Vec<_> = edges
+        .iter()
+        .copied()
+        .filter(|(source, dest)| source != dest)
+        .collect();
+
+    let acyclic = remove_cycles(sizes.len(), &edges);
+    let ranks = assign_ranks(sizes.len(), &acyclic);
+
+    let mut graph = Graph {
+        width: Vec::with_capacity(sizes.len()),
+        rank: Vec::with_capacity(sizes.len()),
+        successors: Vec::with_capacity(sizes.len()),
+        predecessors: Vec::with_capacity(sizes.len()),
+    };
+
+    for (size, rank) in sizes.iter().zip(&ranks) {
+        graph.add_node(size.x, *rank);
+    }
+
+    // The nodes which each edge passes through, including its source and destination
+    let mut routes = Vec::with_capacity(edges.len());
+
+    for &(source, dest) in &acyclic {
+        let mut route = vec![source];
+
+        for rank in ranks[source] + 1..ranks[dest] {
+            let dummy = graph.add_node(0., rank);
+            graph.add_edge(*route.last().unwrap(), dummy);
+            route.push(dummy);
+        }
+
+        graph.add_edge(*route.last().unwrap(), dest);
+        route.push(dest);
+
+        routes.push(route);
+    }
+
+    let rank_count = ranks.iter().max().map_or(0, |rank| rank + 1);
+
+    let order = order_ranks(&graph, rank_count);
+    let cross = position_ranks(&graph, &order);
+
+    // Position of each rank along the main axis
+    let mut rank_starts = Vec::with_capacity(rank_count);
+    let mut rank_heights = vec![0.0_f64; rank_count];
+
+    for (size, rank) in sizes.iter().zip(&ranks) {
+        rank_heights[*rank] = rank_heights[*rank].max(size.y);
+    }
+
+    let mut offset = MARGIN;
+
+    for height in &rank_heights {
+        rank_starts.push(offset);
+        offset += height + RANK_GAP;
+    }
+
+    let main = |node: usize| rank_starts[graph.rank[node]] + rank_heights[graph.rank[node]] / 2.;
+
+    let to_point = |cross: f64, main: f64| match orientation {
+        CourseMapOrientation::TopToBottom => Point::new(cross, main),
+        CourseMapOrientation::LeftToRight => Point::new(main, cross),
+    };
+
+    let nodes = (0..sizes.len())
+        .map(|node| to_point(cross[node], main(node)))
+        .collect();
+
+    let edges = routes
+        .into_iter()
+        .zip(&acyclic)
+        .zip(&edges)
+        .map(|((route, acyclic_edge), edge)| {
+            let mut points: Vec<_> = route
+                .iter()
+                .map(|node| (cross[*node], main(*node)))
+                .collect();
+
+            // Edges start and end at the boundary of their nodes, rather than their centers
+            let last = points.len() - 1;
+            points[0].1 += sizes[route[0]].y / 2.;
+            points[last].1 -= sizes[route[last]].y / 2.;
+
+            if acyclic_edge != edge {
+                points.reverse();
+            }
+
+            points
+                .into_iter()
+                .map(|(cross, main)| to_point(cross, main))
+                .collect()
+        })
+        .collect();
+
+    Layout { nodes, edges }
+}
+
+/// Reverses edges which would form a cycle, based on a depth-first search in node order.
+fn remove_cycles(node_count: usize, edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum VisitState {
+        Unvisited,
+        InProgress,
+        Finished,
+    }
+
+    let mut successors = vec![Vec::new(); node_count];
+
+    for (index, (source, dest)) in edges.iter().enumerate() {
+        successors[*source].push((*dest, index));
+    }
+
+    let mut states = vec![VisitState::Unvisited; node_count];
+    let mut reversed = vec![false; edges.len()];
+
+    for node in 0..node_count {
+        if states[node] != VisitState::Unvisited {
+            continue;
+        }
+
+        // Each stack entry is a node, along with the index of the next edge to follow
+        let mut stack = vec![(node, 0)];
+        states[node] = VisitState::InProgress;
+
+        while let Some((current, index)) = stack.last_mut() {
+            let current = *current;
+
+            if let Some((next, edge)) = successors[current].get(*index).copied() {
+                *index += 1;
+
+                match states[next] {
+                    VisitState::Unvisited => {
+                        states[next] = VisitState::InProgress;
+                        stack.push((next, 0));
+                    }
+                    VisitState::InProgress => reversed[edge] = true,
+                    VisitState::Finished => {}
+                }
+            } else {
+                states[current] = VisitState::Finished;
+                stack.pop();
+            }
+        }
+    }
+
+    edges
+        .iter()
+        .zip(reversed)
+        .map(|((source, dest), reversed)| {
+            if reversed {
+                (*dest, *source)
+            } else {
+                (*source, *dest)
+            }
+        })
+        .collect()
+}
+
+/// Assigns each node to the rank after its furthest (transitive) predecessor.
+fn assign_ranks(node_count: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut ranks = vec![0; node_count];
+    let mut incoming = vec![0_usize; node_count];
+    let mut successors = vec![Vec::new(); node_count];
+
+    for (source, dest) in edges {
+        incoming[*dest] += 1;
+        successors[*source].push(*dest);
+    }
+
+    let mut queue: Vec<_> = (0..node_count)
+        .filter(|node| incoming[*node] == 0)
+        .collect();
+
+    while let Some(node) = queue.pop() {
+        for successor in &successors[node] {
+            ranks[*successor] = ranks[*successor].max(ranks[node] + 1);
+            incoming[*successor] -= 1;
+
+            if incoming[*successor] == 0 {
+                queue.push(*successor);
+            }
+        }
+    }
+
+    ranks
+}
+
+/// Orders the nodes within each rank, by repeatedly moving nodes towards the average position of their neighbors in the previous rank.
+#[allow(clippy::cast_precision_loss)]
+fn order_ranks(graph: &Graph, rank_count: usize) -> Vec<Vec<usize>> {
+    let mut order = vec![Vec::new(); rank_count];
+
+    for (node, rank) in graph.rank.iter().enumerate() {
+        order[*rank].push(node);
+    }
+
+    let mut best = order.clone();
+    let mut best_crossings = count_crossings(graph, &order);
+
+    for pass in 0..ORDERING_PASSES {
+        let downwards = pass % 2 == 0;
+
+        let mut positions = vec![0.; graph.rank.len()];
+
+        for rank in &order {
+            for (index, node) in rank.iter().enumerate() {
+                positions[*node] = index as f64;
+            }
+        }
+
+        let ranks: Vec<usize> = if downwards {
+            (1..rank_count).collect()
+        } else {
+            (0..rank_count.saturating_sub(1)).rev().collect()
+        };
+
+        for rank in ranks {
+            let neighbors = if downwards {
+                &graph.predecessors
+            } else {
+                &graph.successors
+            };
+
+            let mut barycenters: Vec<_> = order[rank]
+                .iter()
+                .map(|node| {
+                    let adjacent = &neighbors[*node];
+
+                    let barycenter = if adjacent.is_empty() {
+                        positions[*node]
+                    } else {
+                        adjacent.iter().map(|n| positions[*n]).sum::<f64>() / adjacent.len() as f64
+                    };
+
+                    (barycenter, *node)
+                })
+                .collect();
+
+            // Stable, so nodes with the same barycenter keep their current order
+            barycenters.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            order[rank] = barycenters.into_iter().map(|(_, node)| node).collect();
+
+            for (index, node) in order[rank].iter().enumerate() {
+                positions[*node] = index as f64;
+            }
+        }
+
+        let crossings = count_crossings(graph, &order);
+
+        if crossings < best_crossings {
+            best.clone_from(&order);
+            best_crossings = crossings;
+        }
+    }
+
+    best
+}
+
+/// Counts the number of edges which cross between adjacent ranks.
+fn count_crossings(graph: &Graph, order: &[Vec<usize>]) -> usize {
+    let mut positions = vec![0; graph.rank.len()];
+
+    for rank in order {
+        for (index, node) in rank.iter().enumerate() {
+            positions[*node] = index;
+        }
+    }
+
+    let mut crossings = 0;
+
+    for rank in order {
+        let edges: Vec<_> = rank
+            .iter()
+            .flat_map(|source| {
+                graph.successors[*source]
+                    .iter()
+                    .map(|dest| (positions[*source], positions[*dest]))
+            })
+            .collect();
+
+        for (index, a) in edges.iter().enumerate() {
+            for b in &edges[index + 1..] {
+                if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                    crossings += 1;
+                }
+            }
+        }
+    }
+
+    crossings
+}
+
+/// Positions the nodes within each rank along the cross axis, moving them towards the nodes they are related to without changing their order.
+#[allow(clippy::cast_precision_loss)]
+fn position_ranks(graph: &Graph, order: &[Vec<usize>]) -> Vec<f64> {
+    let mut cross = vec![0.; graph.rank.len()];
+
+    for rank in order {
+        let mut offset = 0.;
+
+        for node in rank {
+            cross[*node] = offset + graph.width[*node] / 2.;
+            offset += graph.width[*node] + NODE_GAP;
+        }
+    }
+
+    for pass in 0..POSITIONING_PASSES {
+        let downwards = pass % 2 == 0;
+
+        let neighbors = if downwards {
+            &graph.predecessors
+        } else {
+            &graph.successors
+        };
+
+        let ranks: Vec<&Vec<usize>> = if downwards {
+            order.iter().collect()
+        } else {
+            order.iter().rev().collect()
+        };
+
+        for rank in ranks {
+            let desired: HashMap<usize, f64> = rank
+                .iter()
+                .map(|node| {
+                    let adjacent = &neighbors[*node];
+
+                    let position = if adjacent.is_empty() {
+                        cross[*node]
+                    } else {
+                        adjacent.iter().map(|n| cross[*n]).sum::<f64>() / adjacent.len() as f64
+                    };
+
+                    (*node, position)
+                })
+                .collect();
+
+            // Place nodes at their desired position, pushing them apart where they would overlap
+            let mut previous: Option<usize> = None;
+
+            for node in rank {
+                let mut position = desired[node];
+
+                if let Some(previous) = previous {
+                    position = position.max(
+                        cross[previous]
+                            + f64::midpoint(graph.width[previous], graph.width[*node])
+                            + NODE_GAP,
+                    );
+                }
+
+                cross[*node] = position;
+                previous = Some(*node);
+            }
+
+            // Shift the rank back so that nodes are centered on their desired positions overall
+            let shift = rank
+                .iter()
+                .map(|node| desired[node] - cross[*node])
+                .sum::<f64>()
+                / rank.len().max(1) as f64;
+
+            for node in rank {
+                cross[*node] += shift;
+            }
+        }
+    }
+
+    // Move the graph so that it starts at the margin
+    let start = cross
+        .iter()
+        .zip(&graph.width)
+        .map(|(position, width)| position - width / 2.)
+        .fold(f64::INFINITY, f64::min);
+
+    if start.is_finite() {
+        for position in &mut cross {
+            *position += MARGIN - start;
+        }
+    }
+
+    cross
+}
@@ -1,14 +1,30 @@
 #![allow(clippy::module_name_repetitions)]
 #![allow(clippy::doc_markdown)] // Documentation comments are primarily used for JsonSchema
 
-use std::path::{Component, Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
 
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
+mod archive;
+mod cache;
+mod epub;
+pub mod export;
+mod generator;
+pub mod index;
+mod layered;
+pub mod outline;
+pub mod search;
 pub mod storage;
-mod svg;
+pub mod svg;
+#[cfg(test)]
+mod testing;
+pub mod validation;
 
 fn into_relative_path(path: &Path) -> PathBuf {
     let mut new = PathBuf::new();
@@ -39,8 +55,8 @@ fn into_relative_path(path: &Path) -> PathBuf {
 ///
 /// The Course Map's filename must be a UUID in lowercase hexadecimal form without separator characters, with the `.toml`` file extension.
 ///
-/// Note: If the Course Map fails to render, it may crash the rendering task. If this happens, try removing excessive relations or disabling layout optimization.
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+/// Note: If the Course Map's layout fails or takes too long, its items are displayed in a grid without relations. If this happens, try removing excessive relations or disabling layout optimization.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct CourseMap {
     #[serde(skip_deserializing)]
     #[schemars(skip)]
@@ -52,14 +68,51 @@ pub struct CourseMap {
     /// Optimize the CourseMap's layout for visual clarity
     #[serde(default = "default_optimize")]
     pub optimize: bool,
+    /// The direction in which relations are laid out
+    #[serde(default)]
+    pub orientation: CourseMapOrientation,
+    /// The layout engine used to arrange the Course Map's items
+    #[serde(default)]
+    pub layout: CourseMapLayout,
     /// The resources which are a part of this Course Map
     pub courses: Vec<CourseMapItem>,
+    /// The Course Maps referenced by this Course Map's items, which are loaded along with it
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub nested: HashMap<Uuid, CourseMap>,
+    /// The sources of the Course Map and its nested Course Maps, which identify its cached renderings
+    #[serde(skip)]
+    #[schemars(skip)]
+    source: String,
+}
+
+impl CourseMap {
+    /// Returns the UUIDs of every item within the Course Map and its nested Course Maps, in the order they are first specified.
+    pub fn item_ids(&self) -> Vec<Uuid> {
+        let mut ids = Vec::with_capacity(self.courses.len());
+
+        for item in &self.courses {
+            if !ids.contains(&item.uuid) {
+                ids.push(item.uuid);
+            }
+
+            if let Some(nested) = self.nested.get(&item.uuid) {
+                for id in nested.item_ids() {
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+            }
+        }
+
+        ids
+    }
 }
 
 /// A representation of a linked resource within a Course Map
 ///
 /// Items can be specified in any order, and are added to the Course Map in the specified order
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct CourseMapItem {
     /// The unique identifier of a Course or Course Map
     pub uuid: Uuid,
@@ -75,14 +128,63 @@ pub struct CourseMapItem {
     /// Relations are added to the Course Map after all items are added, in the order that they are specified
     #[serde(default)]
     pub relations: Vec<CourseMapRelation>,
+    /// How the item is displayed if it references another Course Map
+    #[serde(default)]
+    pub display: NestedDisplay,
+    /// Optional width of the item, overriding the default width
+    ///
+    /// This can be useful to fit longer labels or to emphasize important items
+    pub width: Option<f64>,
+    /// Optional height of the item, overriding the default height
+    pub height: Option<f64>,
+}
+
+/// Directions in which a Course Map's relations can be laid out
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CourseMapOrientation {
+    /// Items are placed below the items they depend on
+    #[default]
+    TopToBottom,
+    /// Items are placed to the right of the items they depend on
+    LeftToRight,
+}
+
+/// Layout engines which can be used to arrange a Course Map's items
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CourseMapLayout {
+    /// Use the Standard layout, unless the Layered layout has fewer crossing relations or the Standard layout fails
+    #[default]
+    Automatic,
+    /// Lay out items with a general purpose graph layout engine, which respects Layout relations and layout optimization
+    Standard,
+    /// Lay out items in ranks, ordered to minimize crossing relations
+    ///
+    /// This is simpler than the Standard layout, but often displays densely related Course Maps more cleanly.
+    Layered,
+}
+
+/// Ways of displaying an item which references another Course Map
+///
+/// Course Maps can't (directly or indirectly) include themselves. If they do, the item which completes the loop is always displayed as Collapsed.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NestedDisplay {
+    /// Display the item like any other item, which opens the referenced Course Map when clicked
+    #[default]
+    Collapsed,
+    /// Display the referenced Course Map within the item
+    Cluster,
+    /// Replace the item with the items of the referenced Course Map
+    ///
+    /// The item's relations are added to every item of the referenced Course Map which has no relations of its own, and relations whose source is the item are replaced by relations from every item which isn't the source of a relation within the referenced Course Map.
+    Inline,
 }
 
 /// A representation of an item's dependency relation
 ///
 /// Relations are always unidirectional: CourseMapRelation (source) -> CourseMapItem (destination)
 ///
-/// Note: It takes some trial and error to get a Course Map to display relations cleanly. Try rearranging items and/or item relations, toggling layout optimization, and using Layout relations as necessary.
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+/// Note: It takes some trial and error to get a Course Map to display relations cleanly. Try rearranging items and/or item relations, toggling layout optimization, changing the layout engine or orientation, and using Layout relations as necessary.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct CourseMapRelation {
     /// The unique identifier of the (source) item. Must correspond to an existing CourseMapItem object
     pub uuid: Uuid,
@@ -93,10 +195,49 @@ pub struct CourseMapRelation {
     /// Mark a relation as optional
     #[serde(default)]
     pub optional: bool,
+
+    /// Optional short label displayed along the relation, such as "chapters 1-4 only"
+    pub label: Option<String>,
+
+    /// The portion of the (source) item which must be completed to satisfy the relation, ranging between 0 and 1
+    ///
+    /// This is displayed along the relation as a percentage if it is less than 1, and can be used to express partial prerequisites
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+impl CourseMapRelation {
+    /// Returns the completion of the (source) item required to satisfy the relation.
+    pub fn required_completion(&self) -> f32 {
+        if self.weight.is_nan() {
+            1.0
+        } else {
+            self.weight.clamp(0.0, 1.0)
+        }
+    }
+    /// Returns the text displayed along the relation, combining its label and weight.
+    pub fn caption(&self) -> String {
+        let label = self.label.as_deref().map(str::trim).unwrap_or_default();
+        let required = self.required_completion();
+
+        if required >= 1.0 {
+            return label.to_string();
+        }
+
+        let percentage = format!("{}%", (required * 100.0).round());
+
+        if label.is_empty() {
+            percentage
+        } else {
+            format!("{label} ({percentage})")
+        }
+    }
 }
 
 /// Types of item dependency relations
-#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub enum CourseMapRelationType {
     /// Prerequisites are items which should be completed before the following item
     Prerequisite,
@@ -109,7 +250,7 @@ pub enum CourseMapRelationType {
 /// A Course bundle index. Must be a valid TOML file
 ///
 /// Courses are distributed as a folder containing a course.toml at the root. The Course folder's filename must be a UUID in lowercase hexadecimal form without separator characters.
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Course {
     #[schemars(skip)]
     pub uuid: Option<Uuid>,
@@ -119,6 +260,11 @@ pub struct Course {
     pub description: Option<String>,
     /// The textbooks which are a part of this Course
     pub books: Vec<Textbook>,
+    /// Renamed section hrefs within each textbook, keyed by the textbook's progress key (its id, or its position if it has no id), mapping each old href to its new href
+    ///
+    /// When a Course is updated, completed sections and chapter roots using an old href are replaced with the new href, so learners keep their progress. Annotations within a content document which is no longer used by the textbook move to the new href's content document. Each old href is replaced only once, so when renaming a section again, existing entries should be updated to point to the newest href. Entries whose old href is still used by the same textbook are ignored
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub migrations: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 impl Course {
@@ -127,24 +273,79 @@ impl Course {
             book.file = into_relative_path(&book.file);
         }
     }
+    /// Returns a hash of everything which affects how progress through the Course is stored and calculated.
+    ///
+    /// This includes the progress key and chapters of each textbook, along with the Course's migrations. Titles, descriptions, and textbook files are excluded.
+    pub fn fingerprint(&self) -> String {
+        #[derive(Serialize)]
+        struct Structure<'a> {
+            books: Vec<(String, &'a [Chapter])>,
+            migrations: &'a BTreeMap<String, BTreeMap<String, String>>,
+        }
+
+        let structure = Structure {
+            books: self
+                .books
+                .iter()
+                .enumerate()
+                .map(|(index, book)| (book.progress_key(index), book.chapters.as_slice()))
+                .collect(),
+            migrations: &self.migrations,
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(&structure).unwrap_or_default());
+
+        format!("{:x}", hasher.finalize())
+    }
 }
 
 /// A textbook within a Course
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Textbook {
+    /// A stable identifier for the textbook, which must be unique within the Course and must not consist only of digits
+    ///
+    /// Progress through the textbook is stored using this identifier. If it isn't set, progress is stored using the textbook's position within the Course instead, so inserting or reordering textbooks will attach existing progress to the wrong textbook
+    #[serde(default, deserialize_with = "deserialize_textbook_id")]
+    #[schemars(regex(pattern = r"\D"))]
+    pub id: Option<String>,
     /// A short title for the textbook
     pub label: String,
     /// The path of the textbook's corresponding document, relative to the Course index
     ///
-    /// Must resolve to an unpacked EPUB (the EPUB container must be a folder, not a file). EPUB versions 2 - 3.2 are supported
+    /// Must resolve to either an unpacked EPUB folder or a packed `.epub` file. Packed EPUBs are automatically unpacked into a folder with the same name (minus the file extension) when Courses are scanned, and unpacked again whenever they change. If a folder with that name already exists and wasn't unpacked by the app, it's used instead. EPUB versions 2 - 3.2 are supported
     pub file: PathBuf,
     /// A list of user-completable chapters within the textbook
     #[serde(default)]
     pub chapters: Vec<Chapter>,
 }
 
+impl Textbook {
+    /// Returns the key used to store progress through the textbook, given its position within the Course.
+    pub fn progress_key(&self, index: usize) -> String {
+        self.id.clone().unwrap_or_else(|| index.to_string())
+    }
+
+    /// Returns every chapter root and section href used by the textbook.
+    pub fn hrefs(&self) -> HashSet<&str> {
+        let mut hrefs = HashSet::new();
+
+        for chapter in &self.chapters {
+            if let Some(root) = &chapter.root {
+                hrefs.insert(root.as_str());
+            }
+
+            for group in &chapter.groups {
+                hrefs.extend(group.sections.iter().map(String::as_str));
+            }
+        }
+
+        hrefs
+    }
+}
+
 /// A user-completable chapter within a textbook
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Chapter {
     /// The href from the textbook's Table of Contents corresponding to the chapter's root
     pub root: Option<String>,
@@ -161,7 +362,7 @@ pub struct Chapter {
 ///
 /// Section group completion is calculated as:
 /// sectionGroup.completedSections.length / sectionGroup.sections.length
-#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct SectionGroup {
     /// The relative weight of the group's completion
     #[serde(default = "default_weight")]
@@ -170,6 +371,24 @@ pub struct SectionGroup {
     pub sections: Vec<String>,
 }
 
+/// Returns false for textbook ids consisting only of digits, which are reserved for the progress keys of textbooks without an id.
+pub(super) fn is_valid_textbook_id(id: &str) -> bool {
+    !id.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+fn deserialize_textbook_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let id = Option::<String>::deserialize(deserializer)?;
+
+    match id {
+        Some(id) if !is_valid_textbook_id(&id) => Err(D::Error::custom(format!(
+            "textbook id \"{id}\" must contain a character other than a digit"
+        ))),
+        id => Ok(id),
+    }
+}
+
 fn default_optimize() -> bool {
     true
 }
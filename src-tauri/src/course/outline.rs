@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use super::{
+    epub::{Package, TocEntry},
+    Textbook,
+};
+
+/// The position of each chapter of a ``Textbook`` within its reading order, used to find the chapter containing a content document
+pub struct TextbookOutline {
+    /// The position of each content document within the reading order, by href
+    documents: HashMap<String, usize>,
+    /// The position of each chapter's root within the reading order, by chapter index
+    roots: Vec<Option<usize>>,
+    /// The Table of Contents label of each chapter's root, by chapter index
+    labels: Vec<Option<String>>,
+}
+
+impl Textbook {
+    /// Reads the textbook's reading order and Table of Contents to locate its chapters.
+    ///
+    /// If the textbook can't be read, the chapter roots are used as the reading order instead, so only content documents containing a chapter root can be located.
+    pub fn outline(&self) -> TextbookOutline {
+        let package = Package::open(&self.file).ok();
+
+        let documents: HashMap<String, usize> = if let Some(package) = &package {
+            package
+                .spine
+                .iter()
+                .enumerate()
+                .map(|(position, href)| (href.clone(), position))
+                .collect()
+        } else {
+            let mut documents = HashMap::new();
+
+            for root in self.chapters.iter().filter_map(|c| c.root.as_deref()) {
+                let position = documents.len();
+
+                documents
+                    .entry(document_href(root).to_string())
+                    .or_insert(position);
+            }
+
+            documents
+        };
+
+        let entries = package
+            .as_ref()
+            .map(|package| TocEntry::flatten(&package.toc))
+            .unwrap_or_default();
+
+        let mut roots = Vec::with_capacity(self.chapters.len());
+        let mut labels = Vec::with_capacity(self.chapters.len());
+
+        for chapter in &self.chapters {
+            let root = chapter.root.as_deref();
+
+            roots.push(root.and_then(|root| documents.get(document_href(root)).copied()));
+            labels.push(
+                root.and_then(|root| entries.iter().find(|e| e.href.as_deref() == Some(root)))
+                    .map(|entry| entry.label.clone())
+                    .filter(|label| !label.is_empty()),
+            );
+        }
+
+        TextbookOutline {
+            documents,
+            roots,
+            labels,
+        }
+    }
+}
+
+impl TextbookOutline {
+    /// Returns the position of a content document within the textbook's reading order.
+    pub fn position(&self, href: &str) -> Option<usize> {
+        self.documents.get(document_href(href)).copied()
+    }
+    /// Returns the index of the chapter containing a content document, which is the last chapter whose root comes at or before it in reading order.
+    ///
+    /// When several chapters start within the same content document, the first of them is returned.
+    pub fn chapter(&self, href: &str) -> Option<usize> {
+        let position = self.position(href)?;
+
+        let mut chapter: Option<(usize, usize)> = None;
+
+        for (index, root) in self.roots.iter().enumerate() {
+            if let Some(root) = *root {
+                if root <= position && chapter.is_none_or(|(_, best)| root > best) {
+                    chapter = Some((index, root));
+                }
+            }
+        }
+
+        chapter.map(|(index, _)| index)
+    }
+    /// Returns the Table of Contents label of a chapter's root, if it has one.
+    pub fn label(&self, chapter: usize) -> Option<&str> {
+        self.labels.get(chapter)?.as_deref()
+    }
+}
+
+/// Returns the content document part of an href, without its fragment.
+pub fn document_href(href: &str) -> &str {
+    href.split('#').next().unwrap_or_default()
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{self, PoisonError},
+    time::UNIX_EPOCH,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError, Transactional},
+    Batch, Db, Tree,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{
+    epub::{self, Package, TocEntry},
+    Course,
+};
+
+/// The version of the index layout and text extraction
+///
+/// Indexes created with a different version are discarded and rebuilt, as they only contain data which can be read from the resource folder.
+const INDEX_VERSION: u32 = 2;
+
+const VERSION_KEY: &[u8] = b"index_version";
+const COURSES_TREE_KEY: &[u8] = b"courses";
+const DOCUMENTS_TREE_KEY: &[u8] = b"documents";
+const TERMS_TREE_KEY: &[u8] = b"terms";
+
+/// The maximum length of an indexed term in bytes, as longer words are unlikely to be searched for
+const MAX_TERM_LENGTH: usize = 64;
+
+/// The number of characters included before the first match in a snippet. Twice as many are included after it
+const SNIPPET_CONTEXT: usize = 80;
+
+/// Controls how quickly repeated occurrences of a term stop increasing a document's score (BM25's k1 parameter)
+const TERM_SATURATION: f32 = 1.2;
+
+/// Controls how much long documents are penalized (BM25's b parameter)
+const LENGTH_NORMALIZATION: f32 = 0.75;
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+    #[error(transparent)]
+    Storage(#[from] sled::Error),
+    #[error(transparent)]
+    Encoding(#[from] bincode::Error),
+}
+
+impl From<TransactionError<SearchError>> for SearchError {
+    fn from(value: TransactionError<SearchError>) -> Self {
+        match value {
+            TransactionError::Abort(abort) => abort,
+            TransactionError::Storage(error) => Self::Storage(error),
+        }
+    }
+}
+
+/// A content document matching a search query
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchHit {
+    pub course: Uuid,
+    /// The index of the textbook within the Course
+    pub book: usize,
+    /// The href of the content document, relative to the textbook's package document
+    pub href: String,
+    /// The label of the first Table of Contents entry pointing to the document, if there is one
+    pub label: Option<String>,
+    /// An excerpt of the document's text surrounding the first match
+    pub snippet: String,
+    pub score: f32,
+}
+
+/// The indexed documents of a Course, keyed by UUID
+#[derive(Serialize, Deserialize)]
+struct IndexedCourse {
+    /// A hash of the textbook files and content documents which were indexed, used to detect changes
+    signature: String,
+    documents: u32,
+    /// The total number of terms within the Course's documents
+    length: u64,
+}
+
+/// A content document within a textbook, keyed by Course UUID and document number
+#[derive(Serialize, Deserialize)]
+struct IndexedDocument {
+    book: usize,
+    href: String,
+    label: Option<String>,
+    /// The content document's file, which is read again to create snippets
+    path: PathBuf,
+    /// The distinct terms within the document, used to remove its postings
+    terms: Vec<String>,
+}
+
+/// Changes to the index, which are applied in a single transaction
+#[derive(Default)]
+struct Changes {
+    courses: Batch,
+    documents: Batch,
+    terms: Batch,
+}
+
+struct Trees {
+    courses: Tree,
+    documents: Tree,
+    terms: Tree,
+}
+
+/// A persistent inverted index of the text within every textbook in the resource folder
+///
+/// The index is opened when it's first used, so that resources can still be read if it's unavailable.
+pub struct SearchIndex {
+    path: PathBuf,
+    db: sync::Mutex<Option<Db>>,
+}
+
+impl SearchIndex {
+    pub(super) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            db: sync::Mutex::new(None),
+        }
+    }
+    fn open(&self) -> Result<Trees, SearchError> {
+        let mut db = self.db.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let db = if let Some(db) = &*db {
+            db.clone()
+        } else {
+            let opened = sled::open(&self.path)?;
+
+            if opened.get(VERSION_KEY)?.as_deref() != Some(&INDEX_VERSION.to_be_bytes()) {
+                for key in [COURSES_TREE_KEY, DOCUMENTS_TREE_KEY, TERMS_TREE_KEY] {
+                    opened.drop_tree(key)?;
+                }
+
+                opened.insert(VERSION_KEY, &INDEX_VERSION.to_be_bytes())?;
+            }
+
+            db.insert(opened).clone()
+        };
+
+        Ok(Trees {
+            courses: db.open_tree(COURSES_TREE_KEY)?,
+            documents: db.open_tree(DOCUMENTS_TREE_KEY)?,
+            terms: db.open_tree(TERMS_TREE_KEY)?,
+        })
+    }
+    /// Indexes the textbooks of a Course, unless they haven't changed since they were last indexed. Returns whether the Course was indexed.
+    ///
+    /// Textbooks which can't be read are skipped, as they're reported when the Course is validated.
+    pub(super) fn update_course(&self, course: &Course) -> Result<bool, SearchError> {
+        let Some(id) = course.uuid else {
+            return Ok(false);
+        };
+
+        let trees = self.open()?;
+
+        let packages: Vec<_> = course
+            .books
+            .iter()
+            .map(|book| Package::open(&book.file).ok())
+            .collect();
+
+        let signature = signature(course, &packages);
+
+        if let Some(existing) = trees.courses.get(id.as_bytes())? {
+            if decode::<IndexedCourse>(&existing)?.signature == signature {
+                return Ok(false);
+            }
+        }
+
+        let mut changes = removal(&trees, id)?;
+
+        let mut indexed = IndexedCourse {
+            signature,
+            documents: 0,
+            length: 0,
+        };
+
+        for (book, package) in packages.iter().enumerate() {
+            let Some(package) = package else {
+                continue;
+            };
+
+            let mut labels = HashMap::new();
+
+            for entry in TocEntry::flatten(&package.toc) {
+                if let Some(href) = &entry.href {
+                    let path = href.split('#').next().unwrap_or_default();
+
+                    labels.entry(path).or_insert(&entry.label);
+                }
+            }
+
+            for href in &package.spine {
+                let path = package.root.join(epub::decode_href(href));
+
+                let Ok(text) = epub::extract_text(&path) else {
+                    continue;
+                };
+
+                let terms = tokenize(&text);
+
+                if terms.is_empty() {
+                    continue;
+                }
+
+                let mut key = id.as_bytes().to_vec();
+                key.extend(indexed.documents.to_be_bytes());
+
+                let length = u32::try_from(terms.len()).unwrap_or(u32::MAX);
+
+                let mut frequencies: HashMap<String, u32> = HashMap::new();
+
+                for (_, term) in terms {
+                    *frequencies.entry(term).or_default() += 1;
+                }
+
+                let mut document_terms = Vec::with_capacity(frequencies.len());
+
+                for (term, frequency) in frequencies {
+                    let mut posting = frequency.to_be_bytes().to_vec();
+                    posting.extend(length.to_be_bytes());
+
+                    changes.terms.insert(term_key(&term, &key), posting);
+
+                    document_terms.push(term);
+                }
+
+                let document = IndexedDocument {
+                    book,
+                    href: href.clone(),
+                    label: labels.get(href.as_str()).map(|label| (*label).clone()),
+                    path,
+                    terms: document_terms,
+                };
+
+                changes
+                    .documents
+                    .insert(key, bincode::serialize(&document)?);
+
+                indexed.documents += 1;
+                indexed.length += u64::from(length);
+            }
+        }
+
+        changes
+            .courses
+            .insert(id.as_bytes(), bincode::serialize(&indexed)?);
+
+        apply(&trees, &changes)?;
+
+        Ok(true)
+    }
+    /// Removes every Course from the index which isn't included in `courses`.
+    pub(super) fn retain(&self, courses: &HashSet<Uuid>) -> Result<(), SearchError> {
+        let trees = self.open()?;
+
+        for key in trees.courses.iter().keys() {
+            let key = key?;
+
+            let Ok(id) = Uuid::from_slice(&key) else {
+                continue;
+            };
+
+            if !courses.contains(&id) {
+                apply(&trees, &removal(&trees, id)?)?;
+            }
+        }
+
+        Ok(())
+    }
+    /// Removes a single Course from the index.
+    pub(super) fn remove_course(&self, id: Uuid) -> Result<(), SearchError> {
+        let trees = self.open()?;
+
+        apply(&trees, &removal(&trees, id)?)
+    }
+    /// Returns up to `limit` documents containing any of the words within `query`, ranked by relevance.
+    ///
+    /// Documents containing more of the query's words are always ranked first, followed by their BM25 score.
+    pub(super) fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, SearchError> {
+        let terms: HashSet<String> = tokenize(query).into_iter().map(|(_, term)| term).collect();
+
+        if terms.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let trees = self.open()?;
+
+        let mut documents = 0;
+        let mut length = 0;
+
+        for course in trees.courses.iter().values() {
+            let course: IndexedCourse = decode(&course?)?;
+
+            documents += course.documents;
+            length += course.length;
+        }
+
+        if documents == 0 {
+            return Ok(Vec::new());
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let average_length = length as f32 / documents as f32;
+
+        let mut scores: HashMap<Vec<u8>, (usize, f32)> = HashMap::new();
+
+        for term in &terms {
+            let prefix = term_key(term, &[]);
+
+            let mut postings = Vec::new();
+
+            for posting in trees.terms.scan_prefix(&prefix) {
+                let (key, value) = posting?;
+
+                if let (Ok(frequency), Ok(length)) = (
+                    <[u8; 4]>::try_from(value.get(..4).unwrap_or_default()),
+                    <[u8; 4]>::try_from(value.get(4..).unwrap_or_default()),
+                ) {
+                    postings.push((
+                        key[prefix.len()..].to_vec(),
+                        u32::from_be_bytes(frequency),
+                        u32::from_be_bytes(length),
+                    ));
+                }
+            }
+
+            #[allow(clippy::cast_precision_loss)]
+            let matching = postings.len() as f32;
+            #[allow(clippy::cast_precision_loss)]
+            let inverse_frequency =
+                (1.0 + (documents as f32 - matching + 0.5) / (matching + 0.5)).ln();
+
+            for (key, frequency, length) in postings {
+                #[allow(clippy::cast_precision_loss)]
+                let (frequency, length) = (frequency as f32, length as f32);
+
+                let score = inverse_frequency * frequency * (TERM_SATURATION + 1.0)
+                    / (frequency
+                        + TERM_SATURATION
+                            * (1.0 - LENGTH_NORMALIZATION
+                                + LENGTH_NORMALIZATION * length / average_length));
+
+                let entry = scores.entry(key).or_default();
+                entry.0 += 1;
+                entry.1 += score;
+            }
+        }
+
+        let mut ranked: Vec<_> = scores.into_iter().collect();
+        ranked.sort_by(|(a_key, a), (b_key, b)| {
+            b.0.cmp(&a.0)
+                .then(b.1.total_cmp(&a.1))
+                .then(a_key.cmp(b_key))
+        });
+        ranked.truncate(limit);
+
+        let mut hits = Vec::with_capacity(ranked.len());
+
+        for (key, (_, score)) in ranked {
+            let (Some(document), Ok(course)) = (
+                trees.documents.get(&key)?,
+                Uuid::from_slice(&key[..key.len().min(16)]),
+            ) else {
+                continue;
+            };
+
+            let document: IndexedDocument = decode(&document)?;
+
+            // Documents which changed since they were indexed are reindexed during the next scan
+            let snippet = epub::extract_text(&document.path)
+                .map(|text| snippet(&text, &terms))
+                .unwrap_or_default();
+
+            hits.push(SearchHit {
+                course,
+                book: document.book,
+                snippet,
+                href: document.href,
+                label: document.label,
+                score,
+            });
+        }
+
+        Ok(hits)
+    }
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, SearchError> {
+    Ok(bincode::deserialize(data)?)
+}
+
+/// Postings are keyed by term, followed by the key of the document containing it. Terms never contain NUL characters, so a term's postings never overlap with another term's.
+fn term_key(term: &str, document: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(term.len() + 1 + document.len());
+
+    key.extend(term.as_bytes());
+    key.push(0);
+    key.extend(document);
+
+    key
+}
+
+/// Returns the changes needed to remove a Course's documents and postings from the index.
+fn removal(trees: &Trees, id: Uuid) -> Result<Changes, SearchError> {
+    let mut changes = Changes::default();
+
+    changes.courses.remove(id.as_bytes());
+
+    for entry in trees.documents.scan_prefix(id.as_bytes()) {
+        let (key, value) = entry?;
+        let document: IndexedDocument = decode(&value)?;
+
+        for term in &document.terms {
+            changes.terms.remove(term_key(term, &key));
+        }
+
+        changes.documents.remove(key);
+    }
+
+    Ok(changes)
+}
+
+fn apply(trees: &Trees, changes: &Changes) -> Result<(), SearchError> {
+    (&trees.courses, &trees.documents, &trees.terms).transaction(
+        |(courses, documents, terms)| {
+            courses.apply_batch(&changes.courses)?;
+            documents.apply_batch(&changes.documents)?;
+            terms.apply_batch(&changes.terms)?;
+
+            Ok::<_, ConflictableTransactionError<SearchError>>(())
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Hashes everything which affects the indexed documents of a Course: the position and file of each textbook, its Table of Contents, and the size and modification time of each content document.
+fn signature(course: &Course, packages: &[Option<Package>]) -> String {
+    let mut hasher = Sha256::new();
+
+    for (book, package) in course.books.iter().zip(packages) {
+        hasher.update(book.file.to_string_lossy().as_bytes());
+        hasher.update([0]);
+
+        let Some(package) = package else {
+            continue;
+        };
+
+        for entry in TocEntry::flatten(&package.toc) {
+            hasher.update(entry.label.as_bytes());
+            hasher.update([0]);
+            hasher.update(entry.href.as_deref().unwrap_or_default().as_bytes());
+            hasher.update([0]);
+        }
+
+        for href in &package.spine {
+            hasher.update(href.as_bytes());
+            hasher.update([0]);
+
+            if let Ok(metadata) = fs::metadata(package.root.join(epub::decode_href(href))) {
+                hasher.update(metadata.len().to_le_bytes());
+
+                if let Ok(modified) = metadata
+                    .modified()
+                    .map(|modified| modified.duration_since(UNIX_EPOCH).unwrap_or_default())
+                {
+                    hasher.update(modified.as_nanos().to_le_bytes());
+                }
+            }
+        }
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Splits text into lowercase words, along with the byte offset of each word within the text.
+fn tokenize(text: &str) -> Vec<(usize, String)> {
+    let mut terms = Vec::new();
+    let mut start = None;
+
+    for (index, character) in text.char_indices().chain([(text.len(), ' ')]) {
+        if character.is_alphanumeric() {
+            start.get_or_insert(index);
+        } else if let Some(start) = start.take() {
+            let term = text[start..index].to_lowercase();
+
+            if term.len() <= MAX_TERM_LENGTH {
+                terms.push((start, term));
+            }
+        }
+    }
+
+    terms
+}
+
+/// Returns an excerpt of `text` surrounding the first occurrence of any of `terms`, or the start of the text if none occur.
+fn snippet(text: &str, terms: &HashSet<String>) -> String {
+    let position = tokenize(text)
+        .into_iter()
+        .find(|(_, term)| terms.contains(term))
+        .map_or(0, |(offset, _)| offset);
+
+    let mut start = text[..position]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(index, _)| index);
+    let mut end = text[position..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT * 2)
+        .map_or(text.len(), |(index, _)| position + index);
+
+    // Excerpts start and end on word boundaries
+    if start > 0 {
+        start = text[start..position]
+            .find(' ')
+            .map_or(start, |index| start + index + 1);
+    }
+
+    if end < text.len() {
+        end = text[position..end]
+            .rfind(' ')
+            .map_or(end, |index| position + index);
+    }
+
+    let mut snippet = String::new();
+
+    if start > 0 {
+        snippet.push('…');
+    }
+
+    snippet.push_str(&text[start..end]);
+
+    if end < text.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
@@ -1,13 +1,15 @@
 use std::{
     collections::HashSet,
     ffi::OsStr,
-    fs::{self, File},
+    fmt::Write,
+    fs,
     io::{self, ErrorKind},
-    path::PathBuf,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{self, Arc, MutexGuard, PoisonError},
+    time::Duration,
 };
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::{
     sync::Mutex,
@@ -15,9 +17,18 @@ use tokio::{
 };
 use toml::Deserializer;
 use uuid::{fmt::Simple, Uuid};
-use zip::{result::ZipError, ZipArchive};
+use zip::result::ZipError;
 
-use super::{Course, CourseMap};
+use super::{
+    archive,
+    cache::RenderCache,
+    export::{self, ExportError, ExportFormat},
+    index::{ResourceChanges, ResourceIndex},
+    search::{SearchError, SearchHit, SearchIndex},
+    svg::{CourseMapProgress, RenderError},
+    validation::{CourseDiagnostic, CourseMapDiagnostic},
+    Course, CourseMap,
+};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -29,6 +40,24 @@ pub enum Error {
     BlockingTaskFailed(#[from] JoinError),
     #[error(transparent)]
     Decompression(#[from] ZipError),
+    #[error("Archive entry {0} would be extracted outside of the destination folder")]
+    ArchiveEntryOutsideRoot(String),
+    #[error("Archive entry {0} is a symbolic link")]
+    ArchiveSymlink(String),
+    #[error("Archive contains more than {} entries", archive::MAX_ENTRIES)]
+    ArchiveTooManyEntries,
+    #[error("Archive is larger than {} bytes when extracted", archive::MAX_SIZE)]
+    ArchiveTooLarge,
+    #[error("Resource {0} conflicts with another resource with the same UUID")]
+    Conflict(Uuid),
+    #[error("{0} resources within the archive could not be imported")]
+    ImportFailed(usize),
+    #[error("Unable to import archive, it has been moved to {}: {source}", path.display())]
+    Quarantined { path: PathBuf, source: Box<Error> },
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    #[error(transparent)]
+    Search(#[from] SearchError),
 }
 
 async fn get_dir_entries(path: PathBuf) -> Result<Vec<PathBuf>, Error> {
@@ -44,13 +73,19 @@ async fn get_dir_entries(path: PathBuf) -> Result<Vec<PathBuf>, Error> {
     .await?
 }
 
-async fn unpack_dir(root: PathBuf, write_mutex: &Mutex<()>, threads: usize) -> Result<(), Error> {
+async fn unpack_dir(
+    root: PathBuf,
+    write_mutex: &Mutex<()>,
+    threads: usize,
+) -> Result<Vec<ArchiveImport>, Error> {
     let _lock = write_mutex.lock().await;
 
     let entries = get_dir_entries(root.clone()).await?;
 
     let root = Arc::new(root);
 
+    let mut imports = Vec::new();
+
     for path_chunk in entries.chunks(threads) {
         let mut join_set = JoinSet::new();
 
@@ -65,28 +100,337 @@ async fn unpack_dir(root: PathBuf, write_mutex: &Mutex<()>, threads: usize) -> R
             if extension == "zip" {
                 let root = root.clone();
 
-                join_set.spawn_blocking(move || -> Result<(), Error> {
+                join_set.spawn_blocking(move || -> Result<Option<ArchiveImport>, Error> {
                     if path.metadata()?.is_file() {
-                        let file = File::open(&path)?;
-                        let mut archive = ZipArchive::new(file)?;
-
-                        archive.extract(&*root)?;
-                        fs::remove_file(&path)?;
+                        Ok(Some(import_archive(&path, &root)))
+                    } else {
+                        Ok(None)
                     }
-
-                    Ok(())
                 });
             }
         }
 
         while let Some(result) = join_set.join_next().await {
-            result??;
+            if let Some(import) = result?? {
+                imports.push(import);
+            }
+        }
+    }
+
+    Ok(imports)
+}
+
+fn import_archive(path: &Path, root: &Path) -> ArchiveImport {
+    let mut items = Vec::new();
+
+    let result = archive::extract_temporary(path, root).and_then(|temporary| {
+        let result = import_extracted(&temporary, root, &mut items);
+
+        // Resources which weren't imported are still available within the quarantined archive
+        let _ = fs::remove_dir_all(&temporary);
+
+        result
+    });
+
+    ArchiveImport {
+        archive: path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string(),
+        items,
+        error: archive::finish(path, result)
+            .err()
+            .map(|error| format!("{error}")),
+    }
+}
+
+/// Imports the Courses and Course Maps within an extracted archive, adding each of them to `items`.
+///
+/// Every resource is checked before any of them are moved into the resource folder, so either all of them are imported or none of them are.
+fn import_extracted(
+    temporary: &Path,
+    root: &Path,
+    items: &mut Vec<ImportedResource>,
+) -> Result<(), Error> {
+    let mut claimed = HashSet::new();
+    let mut moves = Vec::new();
+
+    for entry in fs::read_dir(temporary)? {
+        let path = entry?.path();
+
+        if let Some((item, target)) = stage_resource(&path, root, &mut claimed) {
+            if item.status != ImportStatus::Failed {
+                moves.push((path, target));
+            }
+
+            items.push(item);
+        }
+    }
+
+    let failed = items
+        .iter()
+        .filter(|i| i.status == ImportStatus::Failed)
+        .count();
+
+    if failed > 0 {
+        mark_failed(
+            items,
+            "Another resource within the archive could not be imported",
+        );
+
+        return Err(Error::ImportFailed(failed));
+    }
+
+    commit_resources(root, &moves).inspect_err(|error| {
+        mark_failed(items, &format!("{error}"));
+    })
+}
+
+/// Marks resources which would have been imported as failed, as the rest of the archive couldn't be imported.
+fn mark_failed(items: &mut [ImportedResource], error: &str) {
+    for item in items {
+        if item.status != ImportStatus::Failed {
+            item.status = ImportStatus::Failed;
+            item.error = Some(error.to_string());
+        }
+    }
+}
+
+/// Checks whether a Course or Course Map from an extracted archive can be imported, returning it along with the path it should be moved to within the resource folder.
+///
+/// The resource's filename is normalized, and resources which can't be imported are returned with the ``ImportStatus::Failed`` status.
+fn stage_resource(
+    path: &Path,
+    root: &Path,
+    claimed: &mut HashSet<Uuid>,
+) -> Option<(ImportedResource, PathBuf)> {
+    let metadata = path.symlink_metadata().ok()?;
+
+    let (kind, filestem) = if metadata.is_dir() {
+        (ResourceKind::Course, path.file_name()?)
+    } else if metadata.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+    {
+        (ResourceKind::CourseMap, path.file_stem()?)
+    } else {
+        return None;
+    };
+
+    let uuid = Uuid::try_parse(&filestem.to_string_lossy()).unwrap_or_else(|_| Uuid::new_v4());
+    let formatted = Simple::from_uuid(uuid)
+        .encode_lower(&mut Uuid::encode_buffer())
+        .to_string();
+
+    let (target, other) = match kind {
+        ResourceKind::Course => (root.join(&formatted), root.join(formatted + ".toml")),
+        ResourceKind::CourseMap => (root.join(formatted.clone() + ".toml"), root.join(formatted)),
+    };
+
+    let mut title = None;
+
+    let result = (|| -> Result<ImportStatus, Error> {
+        if !claimed.insert(uuid) || other.exists() {
+            return Err(Error::Conflict(uuid));
+        }
+
+        title = Some(match kind {
+            ResourceKind::Course => {
+                let data = fs::read_to_string(path.join("course.toml"))?;
+                Course::deserialize(Deserializer::new(&data))?.title
+            }
+            ResourceKind::CourseMap => {
+                let data = fs::read_to_string(path)?;
+                CourseMap::deserialize(Deserializer::new(&data))?.title
+            }
+        });
+
+        Ok(if target.exists() {
+            ImportStatus::Replaced
+        } else {
+            ImportStatus::Added
+        })
+    })();
+
+    let item = match result {
+        Ok(status) => ImportedResource {
+            uuid,
+            kind,
+            title,
+            status,
+            error: None,
+        },
+        Err(error) => ImportedResource {
+            uuid,
+            kind,
+            title,
+            status: ImportStatus::Failed,
+            error: Some(format!("{error}")),
+        },
+    };
+
+    Some((item, target))
+}
+
+/// Moves staged resources into the resource folder, given the path of each resource and the path it should be moved to.
+///
+/// Existing resources are moved aside while they're being replaced, and are only deleted once every resource is in place. If any resource can't be moved, the resources which were already moved are moved back, and the replaced resources are restored.
+fn commit_resources(root: &Path, moves: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let mut replaced = Vec::with_capacity(moves.len());
+
+    for (index, (path, target)) in moves.iter().enumerate() {
+        match move_resource(root, path, target) {
+            Ok(aside) => replaced.push(aside),
+            Err(error) => {
+                for ((path, target), aside) in moves[..index].iter().zip(replaced).rev() {
+                    let _ = fs::rename(target, path);
+
+                    if let Some(aside) = aside {
+                        let _ = fs::rename(aside, target);
+                    }
+                }
+
+                return Err(error);
+            }
         }
     }
 
+    // Every resource has been imported, so failing to delete a replaced resource only leaves behind a hidden file or folder
+    for aside in replaced.into_iter().flatten() {
+        let _ = if aside.is_dir() {
+            fs::remove_dir_all(&aside)
+        } else {
+            fs::remove_file(&aside)
+        };
+    }
+
     Ok(())
 }
 
+/// Moves a resource to `target`, moving the existing resource at `target` (if any) aside and returning its new path.
+fn move_resource(root: &Path, path: &Path, target: &Path) -> Result<Option<PathBuf>, Error> {
+    let aside = if target.exists() {
+        let aside = archive::replaced_path(root);
+        fs::rename(target, &aside)?;
+
+        Some(aside)
+    } else {
+        None
+    };
+
+    if let Err(error) = fs::rename(path, target) {
+        if let Some(aside) = &aside {
+            let _ = fs::rename(aside, target);
+        }
+
+        return Err(error.into());
+    }
+
+    Ok(aside)
+}
+
+fn is_packed_textbook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+}
+
+/// Returns the packed textbooks within a Course folder: those at the top level of the folder, and those referenced by the Course index.
+///
+/// Course folders aren't searched recursively, as unpacked textbooks can contain many files.
+fn find_packed_textbooks(folder: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut textbooks = Vec::new();
+
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_file() && is_packed_textbook(&path) {
+            textbooks.push(path);
+        }
+    }
+
+    // Course indexes which can't be read are reported when the Course is loaded
+    let Ok(data) = fs::read_to_string(folder.join("course.toml")) else {
+        return Ok(textbooks);
+    };
+    let Ok(mut course) = Course::deserialize(Deserializer::new(&data)) else {
+        return Ok(textbooks);
+    };
+
+    course.make_paths_relative();
+
+    for book in course.books {
+        let path = folder.join(book.file);
+
+        if is_packed_textbook(&path) && path.is_file() && !textbooks.contains(&path) {
+            textbooks.push(path);
+        }
+    }
+
+    Ok(textbooks)
+}
+
+async fn unpack_textbooks(
+    root: &Path,
+    courses: &HashSet<Uuid>,
+    write_mutex: &Mutex<()>,
+    threads: usize,
+) -> Result<Vec<ArchiveImport>, Error> {
+    let _lock = write_mutex.lock().await;
+
+    let folders: Vec<PathBuf> = courses
+        .iter()
+        .map(|id| root.join(Simple::from_uuid(*id).encode_lower(&mut Uuid::encode_buffer())))
+        .collect();
+
+    let textbooks = task::spawn_blocking(move || -> Result<Vec<PathBuf>, Error> {
+        let mut textbooks = Vec::new();
+
+        for folder in folders {
+            textbooks.append(&mut find_packed_textbooks(&folder)?);
+        }
+
+        Ok(textbooks)
+    })
+    .await??;
+
+    let mut imports = Vec::with_capacity(textbooks.len());
+
+    for path_chunk in textbooks.chunks(threads) {
+        let mut join_set = JoinSet::new();
+
+        for path in path_chunk {
+            let path = path.clone();
+            let archive = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+
+            join_set.spawn_blocking(move || {
+                match archive::unpack_textbook(&path, &path.with_extension("")) {
+                    Ok(false) => None,
+                    result => Some(ArchiveImport {
+                        archive,
+                        items: Vec::new(),
+                        error: result.err().map(|error| format!("{error}")),
+                    }),
+                }
+            });
+        }
+
+        while let Some(result) = join_set.join_next().await {
+            if let Some(import) = result? {
+                imports.push(import);
+            }
+        }
+    }
+
+    Ok(imports)
+}
+
 async fn handle_dir_entry(path: PathBuf) -> Result<Option<IndexedDirEntry>, Error> {
     task::spawn_blocking(move || {
         let filename = path.file_name().unwrap_or_default().to_string_lossy();
@@ -185,19 +529,188 @@ async fn scan_dir(root: PathBuf, threads: usize) -> Result<DirScanResults, Error
     Ok(results)
 }
 
+fn course_map_path(root: &Path, id: Uuid) -> PathBuf {
+    let mut path = root.join(Simple::from_uuid(id).encode_lower(&mut Uuid::encode_buffer()));
+    path.set_extension("toml");
+
+    path
+}
+
+/// Returns everything which affects a rendering of `course_map` displaying `progress`, which identifies the rendering within the ``RenderCache``.
+fn progress_source(course_map: &CourseMap, progress: &CourseMapProgress) -> String {
+    let mut completions: Vec<_> = progress.iter().collect();
+    completions.sort_unstable_by_key(|(uuid, _)| **uuid);
+
+    let mut source = course_map.source.clone();
+
+    for (uuid, completion) in completions {
+        let _ = write!(source, "\0{uuid}={completion}");
+    }
+
+    source
+}
+
+/// Reads and parses a Course Map, returning it along with its source.
+fn read_course_map_file(root: &Path, id: Uuid) -> Result<(CourseMap, String), Error> {
+    let source = fs::read_to_string(course_map_path(root, id))?;
+    let deserializer = Deserializer::new(&source);
+
+    let mut data = CourseMap::deserialize(deserializer)?;
+    data.uuid = id;
+
+    Ok((data, source))
+}
+
+/// Loads the Course Maps referenced by the items of `course_map` (and the Course Maps referenced by their items), returning the source of each loaded Course Map.
+///
+/// `ancestors` contains the Course Maps which include `course_map`. These are never loaded again, which prevents Course Maps from including themselves.
+fn read_nested_course_maps(
+    root: &Path,
+    course_map: &mut CourseMap,
+    ancestors: &mut Vec<Uuid>,
+    diagnostics: &mut Vec<CourseMapDiagnostic>,
+) -> Vec<String> {
+    let mut sources = Vec::new();
+
+    ancestors.push(course_map.uuid);
+
+    let items: Vec<_> = course_map.courses.iter().map(|item| item.uuid).collect();
+
+    for item in items {
+        if course_map.nested.contains_key(&item) || !course_map_path(root, item).is_file() {
+            continue;
+        }
+
+        if let Some(position) = ancestors.iter().position(|ancestor| *ancestor == item) {
+            diagnostics.push(CourseMapDiagnostic::NestedCycle {
+                path: ancestors[position..].to_vec(),
+            });
+            continue;
+        }
+
+        match read_course_map_file(root, item) {
+            Ok((mut nested, source)) => {
+                sources.push(source);
+                sources.append(&mut read_nested_course_maps(
+                    root,
+                    &mut nested,
+                    ancestors,
+                    diagnostics,
+                ));
+
+                course_map.nested.insert(item, nested);
+            }
+            Err(error) => diagnostics.push(CourseMapDiagnostic::UnreadableNestedMap {
+                item,
+                cause: error.to_string(),
+            }),
+        }
+    }
+
+    ancestors.pop();
+
+    sources
+}
+
+/// A Course Map, along with its rendering, the reason it had to be rendered as a grid (if any), and any problems found in it
+pub type RenderedCourseMap = (
+    CourseMap,
+    String,
+    Option<RenderError>,
+    Vec<CourseMapDiagnostic>,
+);
+
 pub struct DataStore {
     pub root: PathBuf,
     write_mutex: Mutex<()>,
+    index: sync::Mutex<ResourceIndex>,
+    render_cache: Arc<RenderCache>,
+    render_timeout: Duration,
+    search_index: Arc<SearchIndex>,
+    /// Held while the search index is being updated, so that only one update runs at a time
+    search_mutex: Mutex<()>,
 }
 
 impl DataStore {
-    pub fn new(root: PathBuf) -> DataStore {
+    /// Creates a ``DataStore`` for the resource folder at `root`, caching rendered Course Maps in `cache_root` and storing the textbook search index in `search_root`.
+    ///
+    /// Course Maps which take longer than `render_timeout` to lay out are displayed as a grid instead.
+    pub fn new(
+        root: PathBuf,
+        cache_root: PathBuf,
+        search_root: PathBuf,
+        render_timeout: Duration,
+    ) -> DataStore {
         DataStore {
             root,
             write_mutex: Mutex::new(()),
+            index: sync::Mutex::new(ResourceIndex::default()),
+            render_cache: Arc::new(RenderCache::new(cache_root)),
+            render_timeout,
+            search_index: Arc::new(SearchIndex::new(search_root)),
+            search_mutex: Mutex::new(()),
         }
     }
+    fn index(&self) -> MutexGuard<'_, ResourceIndex> {
+        self.index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Starts caching parsed resources in memory.
+    ///
+    /// Once enabled, all changes to the resource folder must be reported through ``invalidate``, otherwise stale resources will be returned.
+    pub fn enable_tracking(&self) {
+        let mut index = self.index();
+
+        index.clear();
+        index.tracking = true;
+    }
+    /// Removes cached resources affected by changes to the given paths, returning the affected resources.
+    pub fn invalidate<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) -> ResourceChanges {
+        let changes = ResourceChanges::from_paths(&self.root, paths);
+
+        if !changes.is_empty() {
+            self.index().apply(&changes);
+        }
+
+        changes
+    }
+    /// Removes all cached resources, for when changes to the resource folder may have been missed.
+    pub fn invalidate_all(&self) -> ResourceChanges {
+        let mut index = self.index();
+
+        let changes = ResourceChanges {
+            courses: index.courses.keys().copied().collect(),
+            course_maps: index.course_maps.keys().copied().collect(),
+            listing: true,
+        };
+
+        index.clear();
+
+        changes
+    }
     pub async fn get_course(&self, id: Uuid) -> Result<Course, Error> {
+        let generation = {
+            let index = self.index();
+
+            if let Some(course) = index.courses.get(&id) {
+                return Ok(course.clone());
+            }
+
+            index.tracking.then_some(index.generation)
+        };
+
+        let course = self.read_course(id).await?;
+
+        if let Some(generation) = generation {
+            let mut index = self.index();
+
+            if index.tracking && index.generation == generation {
+                index.courses.insert(id, course.clone());
+            }
+        }
+
+        Ok(course)
+    }
+    async fn read_course(&self, id: Uuid) -> Result<Course, Error> {
         let root = self
             .root
             .join(Simple::from_uuid(id).encode_lower(&mut Uuid::encode_buffer()));
@@ -214,58 +727,579 @@ impl DataStore {
 
             for book in &mut index.books {
                 book.file = root.join(&book.file);
+
+                // Packed textbooks are unpacked into a sibling folder during scanning, unless unpacking failed
+                if is_packed_textbook(&book.file) {
+                    let unpacked = book.file.with_extension("");
+
+                    if unpacked.is_dir() {
+                        book.file = unpacked;
+                    }
+                }
             }
 
             Ok(index)
         })
         .await?
     }
+    /// Packages a Course into an archive, which can be imported by placing it in the resource folder.
+    ///
+    /// Only the Course index and the textbooks it references are included. If `include_course_maps` is set, every Course Map which references the Course is included as well.
+    pub async fn export_course(
+        &self,
+        id: Uuid,
+        dest: PathBuf,
+        include_course_maps: bool,
+    ) -> Result<(), Error> {
+        let course = self.get_course(id).await?;
+        let root = self.root.clone();
+
+        task::spawn_blocking(move || {
+            let formatted = Simple::from_uuid(id)
+                .encode_lower(&mut Uuid::encode_buffer())
+                .to_string();
+            let course_root = root.join(&formatted);
+
+            let mut sources = vec![(
+                course_root.join("course.toml"),
+                format!("{formatted}/course.toml"),
+            )];
+
+            for book in &course.books {
+                if let Ok(relative) = book.file.strip_prefix(&course_root) {
+                    let name: Vec<_> = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect();
+                    let source = (book.file.clone(), format!("{formatted}/{}", name.join("/")));
+
+                    if !sources.contains(&source) {
+                        sources.push(source);
+                    }
+                }
+            }
+
+            if include_course_maps {
+                for entry in fs::read_dir(&root)? {
+                    let path = entry?.path();
+
+                    if !path.is_file()
+                        || !path
+                            .extension()
+                            .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+                    {
+                        continue;
+                    }
+
+                    let Ok(data) = fs::read_to_string(&path) else {
+                        continue;
+                    };
+                    let Ok(course_map) = CourseMap::deserialize(Deserializer::new(&data)) else {
+                        continue;
+                    };
+
+                    if course_map.courses.iter().any(|item| item.uuid == id) {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        let name = name.to_string();
+
+                        sources.push((path, name));
+                    }
+                }
+            }
+
+            archive::pack(&dest, &sources)
+        })
+        .await?
+    }
+    /// Returns the path of a Course's index.
+    pub fn course_path(&self, id: Uuid) -> PathBuf {
+        self.root
+            .join(Simple::from_uuid(id).encode_lower(&mut Uuid::encode_buffer()))
+            .join("course.toml")
+    }
+    /// Returns the path of a Course Map.
+    pub fn course_map_path(&self, id: Uuid) -> PathBuf {
+        course_map_path(&self.root, id)
+    }
     pub async fn has_course(&self, id: Uuid) -> Result<bool, Error> {
-        let root = self
-            .root
-            .join(Simple::from_uuid(id).encode_lower(&mut Uuid::encode_buffer()));
+        let index_path = self.course_path(id);
+
+        task::spawn_blocking(move || Ok(index_path.exists())).await?
+    }
+
+    pub async fn validate_course(&self, id: Uuid) -> Result<Vec<CourseDiagnostic>, Error> {
+        let course = self.get_course(id).await?;
+
+        Ok(task::spawn_blocking(move || course.validate()).await?)
+    }
+
+    pub async fn get_course_map(&self, id: Uuid) -> Result<RenderedCourseMap, Error> {
+        let generation = {
+            let index = self.index();
+
+            if let Some(course_map) = index.course_maps.get(&id) {
+                return Ok(course_map.clone());
+            }
+
+            index.tracking.then_some(index.generation)
+        };
+
+        let course_map = self.read_course_map(id).await?;
+
+        if let Some(generation) = generation {
+            let mut index = self.index();
+
+            if index.tracking && index.generation == generation {
+                index.course_maps.insert(id, course_map.clone());
+            }
+        }
+
+        Ok(course_map)
+    }
+    /// Returns a Course Map along with its nested Course Maps, without rendering it.
+    ///
+    /// The Course Map is taken from the cache of rendered Course Maps if it's available there.
+    pub async fn get_unrendered_course_map(&self, id: Uuid) -> Result<CourseMap, Error> {
+        if let Some((course_map, _, _, _)) = self.index().course_maps.get(&id) {
+            return Ok(course_map.clone());
+        }
+
+        let root = self.root.clone();
 
         task::spawn_blocking(move || {
-            let index_path = root.join("course.toml");
+            let (mut data, _) = read_course_map_file(&root, id)?;
+
+            read_nested_course_maps(&root, &mut data, &mut Vec::new(), &mut Vec::new());
 
-            Ok(index_path.exists())
+            Ok(data)
         })
         .await?
     }
+    async fn read_course_map(&self, id: Uuid) -> Result<RenderedCourseMap, Error> {
+        let root = self.root.clone();
 
-    pub async fn get_course_map(&self, id: Uuid) -> Result<(CourseMap, String), Error> {
-        let mut path = self
-            .root
-            .join(Simple::from_uuid(id).encode_lower(&mut Uuid::encode_buffer()));
-        path.set_extension("toml");
+        let render_cache = self.render_cache.clone();
+        let render_timeout = self.render_timeout;
 
         task::spawn_blocking(move || {
-            let data = fs::read_to_string(path)?;
-            let deserializer = Deserializer::new(&data);
+            let (mut data, mut source) = read_course_map_file(&root, id)?;
 
-            let mut data = CourseMap::deserialize(deserializer)?;
-            data.uuid = id;
+            let mut diagnostics = data.validate();
 
-            let rendered = data.generate_svg();
+            // Nested Course Maps affect the rendering, so they're included in the cache key
+            for nested_source in
+                read_nested_course_maps(&root, &mut data, &mut Vec::new(), &mut diagnostics)
+            {
+                source.push('\0');
+                source.push_str(&nested_source);
+            }
+
+            let rendered = render_cache.get_or_render(id, None, &source, || {
+                data.try_generate_svg(render_timeout, None)
+            });
+
+            data.source = source;
 
-            Ok((data, rendered))
+            Ok(match rendered {
+                Ok(rendered) => (data, rendered, None, diagnostics),
+                Err(error) => {
+                    let rendered = data.generate_grid_svg(None);
+
+                    (data, rendered, Some(error), diagnostics)
+                }
+            })
         })
         .await?
     }
+    /// Renders a Course Map with the learner's progress displayed on top of its items, along with the reason it had to be rendered as a grid (if any).
+    ///
+    /// The latest rendering (or failed rendering) is cached, so the Course Map is only laid out again when it or the learner's progress changes. Course Maps which couldn't be laid out without progress are displayed as a grid immediately.
+    pub async fn render_course_map_progress(
+        &self,
+        id: Uuid,
+        progress: CourseMapProgress,
+    ) -> Result<(String, Option<RenderError>), Error> {
+        let (course_map, _, error, _) = self.get_course_map(id).await?;
 
+        let render_cache = self.render_cache.clone();
+        let render_timeout = self.render_timeout;
+
+        Ok(task::spawn_blocking(move || {
+            let rendered = match error {
+                Some(error) => Err(error),
+                None => render_cache.get_or_render(
+                    id,
+                    Some("progress"),
+                    &progress_source(&course_map, &progress),
+                    || course_map.try_generate_svg(render_timeout, Some(&progress)),
+                ),
+            };
+
+            match rendered {
+                Ok(rendered) => (rendered, None),
+                Err(error) => (course_map.generate_grid_svg(Some(&progress)), Some(error)),
+            }
+        })
+        .await?)
+    }
+    /// Exports a Course Map to `dest` in the specified format, returning the reason it had to be rendered as a grid (if any).
+    pub async fn export_course_map(
+        &self,
+        id: Uuid,
+        format: ExportFormat,
+        dest: PathBuf,
+    ) -> Result<Option<RenderError>, Error> {
+        let (course_map, _, _, _) = self.get_course_map(id).await?;
+
+        let render_timeout = self.render_timeout;
+
+        task::spawn_blocking(move || {
+            let (data, error) = match format {
+                ExportFormat::Dot => (course_map.generate_dot().into_bytes(), None),
+                ExportFormat::Mermaid => (course_map.generate_mermaid().into_bytes(), None),
+                ExportFormat::Svg => {
+                    let (rendered, error) = course_map.generate_standalone_svg(render_timeout);
+
+                    (rendered.into_bytes(), error)
+                }
+                ExportFormat::Png => {
+                    let (rendered, error) = course_map.generate_standalone_svg(render_timeout);
+
+                    (export::rasterize_svg(&rendered, export::PNG_SCALE)?, error)
+                }
+            };
+
+            fs::write(dest, data)?;
+
+            Ok(error)
+        })
+        .await?
+    }
+
+    /// Imports archives and indexes the resource folder, returning the UUIDs of all available resources.
+    ///
+    /// While tracking is enabled, the previous results are returned (without any imports) if nothing has been added or removed since the last scan.
+    ///
+    /// The search index isn't updated, as indexing textbooks can take much longer than listing them. Call ``DataStore::update_search_index`` afterwards to index any Courses which were found.
     pub async fn scan(&self, threads: usize) -> Result<ScanResult, Error> {
-        unpack_dir(self.root.clone(), &self.write_mutex, threads).await?;
+        let (generation, listing) = {
+            let index = self.index();
+
+            (index.generation, index.listing.clone())
+        };
+
+        if let Some((courses, course_maps)) = listing {
+            return Ok(ScanResult {
+                courses,
+                course_maps,
+                imports: Vec::new(),
+            });
+        }
+
+        let mut imports = unpack_dir(self.root.clone(), &self.write_mutex, threads).await?;
 
         let scan = scan_dir(self.root.clone(), threads).await?;
 
+        imports.append(
+            &mut unpack_textbooks(&self.root, &scan.folders, &self.write_mutex, threads).await?,
+        );
+
+        let courses: Vec<_> = scan.folders.into_iter().collect();
+        let course_maps: Vec<_> = scan.files.into_iter().collect();
+
+        {
+            let mut index = self.index();
+
+            // Changes made by this scan (such as imports) are still reported afterwards, causing a single rescan
+            if index.tracking && index.generation == generation {
+                index.listing = Some((courses.clone(), course_maps.clone()));
+            }
+
+            index.unsearched_listing = Some(courses.clone());
+        }
+
         Ok(ScanResult {
-            courses: scan.folders.into_iter().collect(),
-            course_maps: scan.files.into_iter().collect(),
+            courses,
+            course_maps,
+            imports,
         })
     }
+    /// Returns the UUIDs of all available Courses and Course Maps, without importing archives or unpacking textbooks.
+    ///
+    /// While tracking is enabled, the results of the last full scan are returned if nothing has been added or removed since.
+    pub async fn list(&self, threads: usize) -> Result<(Vec<Uuid>, Vec<Uuid>), Error> {
+        if let Some(listing) = self.index().listing.clone() {
+            return Ok(listing);
+        }
+
+        let scan = scan_dir(self.root.clone(), threads).await?;
+
+        Ok((
+            scan.folders.into_iter().collect(),
+            scan.files.into_iter().collect(),
+        ))
+    }
+    /// Updates the search index with the Courses found by the last full scan, or the Courses which have changed since the index was last updated.
+    ///
+    /// Only Courses which have changed since they were last indexed are reindexed. If the update fails, it's retried by the next update.
+    pub async fn update_search_index(&self, threads: usize) -> Result<(), Error> {
+        let _lock = self.search_mutex.lock().await;
+
+        let (listing, changed) = {
+            let mut index = self.index();
+
+            let changed: Vec<_> = index.unsearched.drain().collect();
+
+            (index.unsearched_listing.take(), changed)
+        };
+
+        let result = match &listing {
+            Some(courses) => self.index_courses(courses, true, threads).await,
+            None => self.index_courses(&changed, false, threads).await,
+        };
+
+        if result.is_err() {
+            let mut index = self.index();
+
+            // A newer listing may have been found while the index was being updated
+            if index.unsearched_listing.is_none() {
+                index.unsearched_listing = listing;
+            }
+
+            index.unsearched.extend(changed);
+        }
+
+        result
+    }
+    /// Indexes the textbooks of the given Courses which have changed since they were last indexed.
+    ///
+    /// If `complete` is set, `courses` must contain every available Course, and all other Courses are removed from the index. Courses which can't be read are removed from the index.
+    async fn index_courses(
+        &self,
+        courses: &[Uuid],
+        complete: bool,
+        threads: usize,
+    ) -> Result<(), Error> {
+        if complete {
+            let search_index = self.search_index.clone();
+            let retained = courses.iter().copied().collect();
+
+            task::spawn_blocking(move || search_index.retain(&retained)).await??;
+        }
+
+        for id_chunk in courses.chunks(threads) {
+            let mut join_set = JoinSet::new();
+
+            for id in id_chunk {
+                let id = *id;
+                let course = self.get_course(id).await;
+                let search_index = self.search_index.clone();
+
+                join_set.spawn_blocking(move || match course {
+                    Ok(course) => search_index.update_course(&course).map(|_| ()),
+                    Err(_) => search_index.remove_course(id),
+                });
+            }
+
+            while let Some(result) = join_set.join_next().await {
+                result??;
+            }
+        }
+
+        Ok(())
+    }
+    /// Searches the text of every indexed textbook, returning up to `limit` matching content documents ranked by relevance.
+    ///
+    /// Courses which haven't been indexed yet (see ``DataStore::update_search_index``) aren't included in the results.
+    pub async fn search(&self, query: String, limit: usize) -> Result<Vec<SearchHit>, Error> {
+        let search_index = self.search_index.clone();
+
+        Ok(task::spawn_blocking(move || search_index.search(&query, limit)).await??)
+    }
 }
 
+#[derive(Serialize)]
 pub struct ScanResult {
     pub courses: Vec<Uuid>,
     pub course_maps: Vec<Uuid>,
+    /// The archives (resource bundles and packed textbooks) which were imported during the scan
+    pub imports: Vec<ArchiveImport>,
+}
+
+/// The outcome of importing a single archive
+#[derive(Serialize, Debug)]
+pub struct ArchiveImport {
+    /// The path of the archive, relative to the resource folder
+    pub archive: String,
+    /// The Courses and Course Maps contained in the archive. Always empty for packed textbooks
+    pub items: Vec<ImportedResource>,
+    /// The reason the archive couldn't be imported, if any part of the import failed
+    ///
+    /// Resource bundles are quarantined when this happens, while packed textbooks are left in place and unpacked again during the next scan
+    pub error: Option<String>,
+}
+
+/// A Course or Course Map contained in an imported archive
+#[derive(Serialize, Debug)]
+pub struct ImportedResource {
+    pub uuid: Uuid,
+    pub kind: ResourceKind,
+    /// The title of the resource, if its index could be read
+    pub title: Option<String>,
+    pub status: ImportStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Course,
+    CourseMap,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStatus {
+    /// No resource with the same UUID existed before the import
+    Added,
+    /// The resource replaced an existing resource with the same UUID
+    Replaced,
+    /// The resource could not be imported, and was left inside the quarantined archive
+    Failed,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Write,
+        path::{Path, PathBuf},
+    };
+
+    use zip::write::SimpleFileOptions;
+
+    use super::{
+        super::testing::{write_archive, TemporaryFolder},
+        find_packed_textbooks, import_archive, ImportStatus,
+    };
+
+    const EXISTING: &str = "0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a.toml";
+    const ADDED: &str = "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b.toml";
+
+    /// Creates a resource folder containing a single Course Map.
+    fn resource_folder() -> TemporaryFolder {
+        let root = TemporaryFolder::new();
+        fs::write(root.join(EXISTING), course_map("Existing")).unwrap();
+
+        root
+    }
+
+    fn course_map(title: &str) -> String {
+        format!("title = \"{title}\"\ncourses = []\n")
+    }
+
+    /// Writes an archive containing each file to the resource folder.
+    fn write_resources(root: &Path, files: &[(&str, &str)]) -> PathBuf {
+        write_archive(root, "resources.zip", |writer| {
+            for (name, contents) in files {
+                writer
+                    .start_file(*name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+        })
+    }
+
+    /// Returns the sorted filenames within the resource folder.
+    fn filenames(root: &Path) -> Vec<String> {
+        let mut filenames: Vec<_> = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        filenames.sort();
+
+        filenames
+    }
+
+    #[test]
+    fn replaces_existing_resources() {
+        let root = resource_folder();
+        let archive = write_resources(
+            &root,
+            &[
+                (EXISTING, &course_map("Replacement")),
+                (ADDED, &course_map("Added")),
+            ],
+        );
+
+        let import = import_archive(&archive, &root);
+
+        assert!(import.error.is_none());
+        assert_eq!(import.items.len(), 2);
+
+        for item in &import.items {
+            let expected = if EXISTING.starts_with(&item.uuid.simple().to_string()) {
+                ImportStatus::Replaced
+            } else {
+                ImportStatus::Added
+            };
+
+            assert_eq!(item.status, expected);
+        }
+
+        assert_eq!(filenames(&root), [EXISTING, ADDED]);
+        assert_eq!(
+            fs::read_to_string(root.join(EXISTING)).unwrap(),
+            course_map("Replacement")
+        );
+    }
+
+    #[test]
+    fn imports_nothing_if_any_resource_fails() {
+        let root = resource_folder();
+        let archive = write_resources(
+            &root,
+            &[(EXISTING, &course_map("Replacement")), (ADDED, "title = ")],
+        );
+
+        let import = import_archive(&archive, &root);
+
+        assert!(import.error.is_some());
+        assert!(import
+            .items
+            .iter()
+            .all(|item| item.status == ImportStatus::Failed && item.error.is_some()));
+        assert_eq!(filenames(&root), [EXISTING, "resources.zip.quarantined"]);
+        assert_eq!(
+            fs::read_to_string(root.join(EXISTING)).unwrap(),
+            course_map("Existing")
+        );
+    }
+
+    #[test]
+    fn finds_referenced_and_top_level_textbooks() {
+        let folder = TemporaryFolder::new();
+
+        for path in ["top.epub", "books/referenced.epub", "books/ignored.epub"] {
+            let path = folder.join(path);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        fs::write(
+            folder.join("course.toml"),
+            "title = \"Course\"\nbooks = [{ label = \"Referenced\", file = \"books/referenced.epub\" }]\n",
+        )
+        .unwrap();
+
+        let mut textbooks = find_packed_textbooks(&folder).unwrap();
+        textbooks.sort();
+
+        assert_eq!(
+            textbooks,
+            [
+                folder.join("books/referenced.epub"),
+                folder.join("top.epub")
+            ]
+        );
+    }
 }
@@ -1,4 +1,12 @@
-use std::collections::HashMap;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
 
 use layout::{
     core::{
@@ -11,123 +19,619 @@ use layout::{
     std_shapes::shapes::{Arrow, Element, LineEndKind, ShapeKind},
     topo::layout::VisualGraph,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::{from_str, to_string};
+use thiserror::Error;
+use uuid::Uuid;
 
-use super::{CourseMap, CourseMapItem, CourseMapRelationType};
+use super::{
+    layered, CourseMap, CourseMapItem, CourseMapLayout, CourseMapOrientation, CourseMapRelation,
+    CourseMapRelationType, NestedDisplay,
+};
 
 pub(super) const SIZE: f64 = 128.0;
 pub(super) const RATIO: f64 = 1.2;
 pub(super) const LINE_WIDTH: usize = 2;
 
+/// The version of the Course Map renderer, used to invalidate cached renderings
+///
+/// This must be incremented whenever a change would alter the rendering of an existing Course Map.
+pub(super) const RENDERER_VERSION: u32 = 5;
+
+/// The default time limit for laying out a single Course Map
+pub const DEFAULT_RENDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The space between items when falling back to a grid layout
+const GRID_GAP: f64 = SIZE / 4.;
+
+/// The smallest size which can be specified for an item
+const MIN_ITEM_SIZE: f64 = SIZE / 4.;
+
+/// The largest size which can be specified for an item
+const MAX_ITEM_SIZE: f64 = SIZE * 8.;
+
+/// The font size of item labels in standalone SVGs
+const LABEL_FONT_SIZE: f64 = 16.;
+
+/// The fonts used for text in standalone SVGs, which can't inherit fonts from the app
+const STANDALONE_FONT_FAMILY: &str = "Helvetica, Arial, DejaVu Sans, sans-serif";
+
+/// The height of the label above a nested Course Map displayed as a Cluster
+const CLUSTER_HEADER: f64 = SIZE / RATIO / 2.;
+
+/// The completion (ranging between 0 and 1) of each completable Course within a Course Map, used to display the learner's progress on top of its items
+pub type CourseMapProgress = HashMap<Uuid, f32>;
+
+/// The learner's progress through a single Course Map item
+#[derive(Debug, Clone, Copy)]
+struct ItemState {
+    completion: Option<f32>,
+    locked: bool,
+}
+
+/// The rendering of a nested Course Map, which is displayed within an item
+#[derive(Clone)]
+struct Cluster {
+    size: Point,
+    content: String,
+}
+
+impl Cluster {
+    /// The size of the item containing the nested Course Map, which is at least `minimum`
+    ///
+    /// This is rounded up to whole pixels, as layout can fail due to rounding errors when items have fractional sizes.
+    fn item_size(&self, minimum: Point) -> Point {
+        Point::new(
+            self.size.x.max(minimum.x).ceil(),
+            (self.size.y + CLUSTER_HEADER).max(minimum.y).ceil(),
+        )
+    }
+}
+
+/// The reason a Course Map couldn't be laid out, in which case it is displayed as a grid instead
+#[derive(Error, Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum RenderError {
+    #[error("Course Map layout failed: {message}")]
+    Panicked { message: String },
+    #[error("Course Map layout did not finish within {timeout_ms}ms")]
+    TimedOut { timeout_ms: u128 },
+}
+
 #[allow(clippy::cast_possible_truncation)]
 #[allow(clippy::cast_sign_loss)]
 const RADIUS: usize = (SIZE / 16.) as usize;
 
 impl CourseMap {
-    /// Creates a visual representation of a ``CourseMap`` as an SVG.
-    pub fn generate_svg(&self) -> String {
+    /// Creates a self-contained visual representation of a ``CourseMap`` as an SVG, which can be displayed outside of the app.
+    ///
+    /// Unlike ``CourseMap::try_generate_svg``, item labels are drawn as SVG text rather than HTML, as most tools can't display HTML within an SVG. If layout fails or takes longer than `timeout`, the items are placed in a grid instead.
+    pub fn generate_standalone_svg(&self, timeout: Duration) -> (String, Option<RenderError>) {
+        match self.try_render(timeout, None, true) {
+            Ok(rendered) => (rendered, None),
+            Err(error) => (self.render_grid(None, true), Some(error)),
+        }
+    }
+    fn render(&self, progress: Option<&CourseMapProgress>, standalone: bool) -> String {
         if self.courses.is_empty() {
             return String::new();
         }
 
-        let mut graph = self.generate_graph();
-
         let mut writer = SVGWriter::new();
+        writer.standalone = standalone;
 
-        graph.do_it(false, !self.optimize, false, &mut writer);
+        self.draw(&mut writer, progress);
 
         writer.finalize()
     }
-    fn generate_graph(&self) -> VisualGraph {
-        let mut graph = VisualGraph::new(Orientation::TopToBottom);
+    /// Lays out the Course Map (including nested Course Maps) and draws it using `writer`.
+    fn draw(&self, writer: &mut SVGWriter, progress: Option<&CourseMapProgress>) {
+        let items = self.expand_items();
 
-        let mut nodes = HashMap::with_capacity(self.courses.len());
+        writer.item_states = Self::item_states(&items, progress);
+        writer.clusters = self.generate_clusters(&items, writer, progress);
 
-        let mut colors = HashMap::with_capacity(self.courses.len());
+        match self.layout {
+            CourseMapLayout::Standard => self.draw_standard(&items, writer),
+            CourseMapLayout::Layered => self.draw_layered(&items, writer),
+            CourseMapLayout::Automatic => {
+                let mut standard = writer.clone();
 
-        let relation_style = StyleAttr {
-            line_color: Color::new(0x00_00_00_ff),
-            line_width: LINE_WIDTH,
-            fill_color: None,
-            rounded: 0,
-            font_size: 8,
-        };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    self.draw_standard(&items, &mut standard);
+                }));
 
-        let node_style = StyleAttr {
-            line_color: Color::new(0x00_00_00_ff),
-            line_width: LINE_WIDTH,
-            fill_color: Some(Color::new(0xff_ff_ff_ff)),
-            rounded: RADIUS,
-            font_size: 8,
-        };
+                let standard_crossings = count_crossings(&standard.arrows);
 
-        for course in &self.courses {
-            let mut style = node_style.clone();
+                if result.is_ok() && standard_crossings == 0 {
+                    *writer = standard;
+                    return;
+                }
 
-            if let Some(color) = Color::from_name(&course.color.to_ascii_lowercase()) {
-                style.line_color = color;
+                let mut layered = writer.clone();
+                self.draw_layered(&items, &mut layered);
 
-                colors.insert(course.uuid, color);
+                if result.is_err() || count_crossings(&layered.arrows) < standard_crossings {
+                    *writer = layered;
+                } else {
+                    *writer = standard;
+                }
+            }
+        }
+    }
+    /// Lays out the items using layout-rs and draws them using `writer`.
+    fn draw_standard(&self, items: &[CourseMapItem], writer: &mut SVGWriter) {
+        let mut graph = generate_graph(items, &writer.clusters, self.orientation);
+
+        graph.do_it(false, !self.optimize, false, writer);
+    }
+    /// Lays out the items in ranks (see ``layered::layout``) and draws them using `writer`.
+    fn draw_layered(&self, items: &[CourseMapItem], writer: &mut SVGWriter) {
+        let colors = item_colors(items);
+
+        let sizes: Vec<_> = items
+            .iter()
+            .map(|item| item_size(item, &writer.clusters))
+            .collect();
+
+        let indexes: HashMap<_, _> = items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (item.uuid, index))
+            .collect();
+
+        let mut edges = Vec::new();
+        let mut arrows = Vec::new();
+
+        for (dest, item) in items.iter().enumerate() {
+            for relation in &item.relations {
+                // Self-relations are reported as diagnostics, and aren't laid out
+                if let Some(&source) = indexes.get(&relation.uuid).filter(|&&s| s != dest) {
+                    edges.push((source, dest));
+                    arrows.push(relation_arrow(relation, &colors));
+                }
+            }
+        }
+
+        let layout = layered::layout(&sizes, &edges, self.orientation);
+
+        for ((item, center), size) in items.iter().zip(&layout.nodes).zip(&sizes) {
+            let style = node_style(colors.get(&item.uuid).copied());
+
+            writer.draw_rect(center.sub(size.scale(0.5)), *size, &style, None);
+            writer.draw_text(*center, &to_string(item).unwrap(), &style);
+        }
+
+        for (arrow, points) in arrows.iter().zip(&layout.edges) {
+            let dashed = match arrow.line_style {
+                LineStyleKind::None => continue,
+                LineStyleKind::Normal => false,
+                LineStyleKind::Dashed | LineStyleKind::Dotted => true,
+            };
+
+            // Curves leave and enter each point along the direction of the layout
+            let along = |vector: Point| match self.orientation {
+                CourseMapOrientation::TopToBottom => Point::new(0., vector.y / 2.),
+                CourseMapOrientation::LeftToRight => Point::new(vector.x / 2., 0.),
+            };
+
+            let mut path = vec![(points[0], points[0].add(along(points[1].sub(points[0]))))];
+
+            for pair in points.windows(2) {
+                path.push((pair[1].sub(along(pair[1].sub(pair[0]))), pair[1]));
             }
 
-            let node = Element {
-                shape: ShapeKind::Box(to_string(course).unwrap()),
-                look: style,
-                orientation: Orientation::LeftToRight,
-                pos: Position::new(
-                    Point::zero(),
-                    Point::new(SIZE * RATIO, SIZE / RATIO),
-                    Point::zero(),
-                    Point::new((SIZE * RATIO) * 0.75, (SIZE / RATIO) * 0.75),
+            writer.draw_arrow(
+                &path,
+                dashed,
+                (
+                    matches!(arrow.start, LineEndKind::Arrow),
+                    matches!(arrow.end, LineEndKind::Arrow),
                 ),
+                &arrow.look,
+                &arrow.text,
+            );
+        }
+    }
+    /// Returns the items to display, replacing items which are displayed Inline with the items of the Course Map they reference.
+    pub(super) fn expand_items(&self) -> Vec<CourseMapItem> {
+        let mut items: Vec<CourseMapItem> = Vec::with_capacity(self.courses.len());
+
+        // Maps each Inline item to the items which replace it as the source of relations
+        let mut exits: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+
+        for item in &self.courses {
+            let nested = self.nested.get(&item.uuid).filter(|nested| {
+                item.display == NestedDisplay::Inline && !nested.courses.is_empty()
+            });
+
+            let Some(nested) = nested else {
+                items.push(item.clone());
+                continue;
             };
 
-            nodes.insert(course.uuid, graph.add_node(node));
+            let nested_items = nested.expand_items();
+
+            let sources: HashSet<_> = nested_items
+                .iter()
+                .flat_map(|nested_item| nested_item.relations.iter().map(|r| r.uuid))
+                .collect();
+
+            exits.insert(
+                item.uuid,
+                nested_items
+                    .iter()
+                    .map(|nested_item| nested_item.uuid)
+                    .filter(|uuid| !sources.contains(uuid))
+                    .collect(),
+            );
+
+            for mut nested_item in nested_items {
+                if self.courses.iter().any(|i| i.uuid == nested_item.uuid)
+                    || items.iter().any(|i| i.uuid == nested_item.uuid)
+                {
+                    continue;
+                }
+
+                if nested_item.relations.is_empty() {
+                    nested_item.relations.clone_from(&item.relations);
+                }
+
+                items.push(nested_item);
+            }
         }
 
-        for course in &self.courses {
-            if let Some(dest) = nodes.get(&course.uuid) {
-                for relation in &course.relations {
-                    if let Some(source) = nodes.get(&relation.uuid) {
-                        let arrow = if relation.r#type == CourseMapRelationType::Layout {
-                            Arrow::invisible()
-                        } else {
-                            let mut style = relation_style.clone();
-
-                            if let Some(color) = colors.get(&relation.uuid) {
-                                style.line_color = *color;
-                            }
-
-                            let end = match relation.r#type {
-                                CourseMapRelationType::Prerequisite => LineEndKind::Arrow,
-                                CourseMapRelationType::Corequisite
-                                | CourseMapRelationType::Layout => LineEndKind::None,
-                            };
-
-                            let line_style = if relation.optional {
-                                LineStyleKind::Dashed
-                            } else {
-                                LineStyleKind::Normal
-                            };
-
-                            Arrow {
-                                start: LineEndKind::None,
-                                end,
-                                line_style,
-                                text: String::new(),
-                                look: style,
-                                src_port: None,
-                                dst_port: None,
-                            }
-                        };
-
-                        graph.add_edge(arrow, *source, *dest);
+        if exits.is_empty() {
+            return items;
+        }
+
+        for item in &mut items {
+            for relation in mem::take(&mut item.relations) {
+                if let Some(replacements) = exits.get(&relation.uuid) {
+                    for uuid in replacements {
+                        item.relations.push(CourseMapRelation {
+                            uuid: *uuid,
+                            ..relation.clone()
+                        });
                     }
+                } else {
+                    item.relations.push(relation);
                 }
             }
         }
 
-        graph
+        items
+    }
+    /// Renders the nested Course Maps of items which are displayed as a Cluster.
+    fn generate_clusters(
+        &self,
+        items: &[CourseMapItem],
+        writer: &mut SVGWriter,
+        progress: Option<&CourseMapProgress>,
+    ) -> HashMap<Uuid, Cluster> {
+        let mut clusters = HashMap::new();
+
+        for item in items {
+            if item.display != NestedDisplay::Cluster {
+                continue;
+            }
+
+            let Some(nested) = self.nested.get(&item.uuid) else {
+                continue;
+            };
+
+            if nested.courses.is_empty() {
+                continue;
+            }
+
+            // Relations are identified by a counter, which must be unique across all nested Course Maps
+            let mut nested_writer = SVGWriter::new();
+            nested_writer.counter = writer.counter;
+            nested_writer.standalone = writer.standalone;
+
+            nested.draw(&mut nested_writer, progress);
+
+            writer.counter = nested_writer.counter;
+
+            clusters.insert(
+                item.uuid,
+                Cluster {
+                    size: nested_writer.view_size,
+                    content: nested_writer.content,
+                },
+            );
+        }
+
+        clusters
+    }
+    /// Creates a visual representation of a ``CourseMap`` as an SVG on a separate thread, failing if layout panics or takes longer than `timeout`.
+    ///
+    /// If `progress` is specified, each item displays its completion, whether it has been completed, and whether it is locked by incomplete prerequisites.
+    ///
+    /// Layout can't be interrupted, so a timed out rendering thread continues running in the background until it finishes.
+    pub fn try_generate_svg(
+        &self,
+        timeout: Duration,
+        progress: Option<&CourseMapProgress>,
+    ) -> Result<String, RenderError> {
+        self.try_render(timeout, progress, false)
+    }
+    fn try_render(
+        &self,
+        timeout: Duration,
+        progress: Option<&CourseMapProgress>,
+        standalone: bool,
+    ) -> Result<String, RenderError> {
+        let course_map = self.clone();
+        let progress = progress.cloned();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                course_map.render(progress.as_ref(), standalone)
+            }));
+
+            let _ = sender.send(result.map_err(|payload| panic_message(&*payload)));
+        });
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result.map_err(|message| RenderError::Panicked { message }),
+            Err(RecvTimeoutError::Timeout) => Err(RenderError::TimedOut {
+                timeout_ms: timeout.as_millis(),
+            }),
+            Err(RecvTimeoutError::Disconnected) => Err(RenderError::Panicked {
+                message: "Rendering thread exited unexpectedly".to_string(),
+            }),
+        }
+    }
+    /// Creates a visual representation of a ``CourseMap`` as an SVG, without performing any layout.
+    ///
+    /// Items are placed in a grid in the order they are specified, and relations are not displayed.
+    pub fn generate_grid_svg(&self, progress: Option<&CourseMapProgress>) -> String {
+        self.render_grid(progress, false)
+    }
+    #[allow(clippy::cast_precision_loss)]
+    fn render_grid(&self, progress: Option<&CourseMapProgress>, standalone: bool) -> String {
+        if self.courses.is_empty() {
+            return String::new();
+        }
+
+        let mut columns = 1;
+
+        while columns * columns < self.courses.len() {
+            columns += 1;
+        }
+
+        let mut writer = SVGWriter::new();
+        writer.standalone = standalone;
+        writer.item_states = Self::item_states(&self.courses, progress);
+
+        // Every cell is large enough to fit the largest item
+        let cell = self
+            .courses
+            .iter()
+            .map(|course| item_size(course, &writer.clusters))
+            .fold(Point::zero(), |cell, size| {
+                Point::new(cell.x.max(size.x), cell.y.max(size.y))
+            });
+
+        for (index, course) in self.courses.iter().enumerate() {
+            let style = node_style(Color::from_name(&course.color.to_ascii_lowercase()));
+            let size = item_size(course, &writer.clusters);
+
+            let xy = Point::new(
+                GRID_GAP + (index % columns) as f64 * (cell.x + GRID_GAP),
+                GRID_GAP + (index / columns) as f64 * (cell.y + GRID_GAP),
+            );
+
+            writer.draw_rect(xy, size, &style, None);
+            writer.draw_text(
+                Point::new(xy.x + size.x / 2., xy.y + size.y / 2.),
+                &to_string(course).unwrap(),
+                &style,
+            );
+        }
+
+        writer.finalize()
+    }
+    /// Determines the state of each item from the learner's progress.
+    ///
+    /// An item is locked if any of its required (non-optional) prerequisites haven't reached the completion required by the relation's weight. Prerequisites which aren't completable Courses are never complete.
+    fn item_states(
+        items: &[CourseMapItem],
+        progress: Option<&CourseMapProgress>,
+    ) -> HashMap<Uuid, ItemState> {
+        let Some(progress) = progress else {
+            return HashMap::new();
+        };
+
+        let mut states = HashMap::with_capacity(items.len());
+
+        for course in items {
+            let locked = course.relations.iter().any(|relation| {
+                relation.r#type == CourseMapRelationType::Prerequisite
+                    && !relation.optional
+                    && progress
+                        .get(&relation.uuid)
+                        .is_none_or(|completion| *completion < relation.required_completion())
+            });
+
+            states.insert(
+                course.uuid,
+                ItemState {
+                    completion: progress.get(&course.uuid).copied(),
+                    locked,
+                },
+            );
+        }
+
+        states
+    }
+}
+
+fn generate_graph(
+    items: &[CourseMapItem],
+    clusters: &HashMap<Uuid, Cluster>,
+    orientation: CourseMapOrientation,
+) -> VisualGraph {
+    let mut graph = VisualGraph::new(match orientation {
+        CourseMapOrientation::TopToBottom => Orientation::TopToBottom,
+        CourseMapOrientation::LeftToRight => Orientation::LeftToRight,
+    });
+
+    let mut nodes = HashMap::with_capacity(items.len());
+
+    let colors = item_colors(items);
+
+    for course in items {
+        let style = node_style(colors.get(&course.uuid).copied());
+
+        let node = Element {
+            shape: ShapeKind::Box(to_string(course).unwrap()),
+            look: style,
+            orientation: Orientation::LeftToRight,
+            pos: Position::new(
+                Point::zero(),
+                item_size(course, clusters),
+                Point::zero(),
+                Point::new((SIZE * RATIO) * 0.75, (SIZE / RATIO) * 0.75),
+            ),
+        };
+
+        nodes.insert(course.uuid, graph.add_node(node));
+    }
+
+    for course in items {
+        if let Some(dest) = nodes.get(&course.uuid) {
+            for relation in &course.relations {
+                if let Some(source) = nodes.get(&relation.uuid) {
+                    graph.add_edge(relation_arrow(relation, &colors), *source, *dest);
+                }
+            }
+        }
+    }
+
+    graph
+}
+
+/// Returns the accent color of each item which has a valid color.
+fn item_colors(items: &[CourseMapItem]) -> HashMap<Uuid, Color> {
+    items
+        .iter()
+        .filter_map(|course| {
+            Color::from_name(&course.color.to_ascii_lowercase()).map(|color| (course.uuid, color))
+        })
+        .collect()
+}
+
+/// Returns the size of an item, using the default size unless it is specified by the Course Map.
+///
+/// Specified sizes are limited to a reasonable range and rounded up to whole pixels (see ``Cluster::item_size``).
+fn item_size(item: &CourseMapItem, clusters: &HashMap<Uuid, Cluster>) -> Point {
+    let specified = |size: Option<f64>, default: f64| {
+        size.filter(|size| size.is_finite())
+            .map_or(default, |size| {
+                size.clamp(MIN_ITEM_SIZE, MAX_ITEM_SIZE).ceil()
+            })
+    };
+
+    let size = Point::new(
+        specified(item.width, SIZE * RATIO),
+        specified(item.height, SIZE / RATIO),
+    );
+
+    clusters
+        .get(&item.uuid)
+        .map_or(size, |cluster| cluster.item_size(size))
+}
+
+/// Creates the arrow which represents a relation, colored using the accent color of its source item.
+fn relation_arrow(relation: &CourseMapRelation, colors: &HashMap<Uuid, Color>) -> Arrow {
+    if relation.r#type == CourseMapRelationType::Layout {
+        return Arrow::invisible();
+    }
+
+    let mut style = StyleAttr {
+        line_color: Color::new(0x00_00_00_ff),
+        line_width: LINE_WIDTH,
+        fill_color: None,
+        rounded: 0,
+        font_size: 12,
+    };
+
+    if let Some(color) = colors.get(&relation.uuid) {
+        style.line_color = *color;
+    }
+
+    let end = match relation.r#type {
+        CourseMapRelationType::Prerequisite => LineEndKind::Arrow,
+        CourseMapRelationType::Corequisite | CourseMapRelationType::Layout => LineEndKind::None,
+    };
+
+    let line_style = if relation.optional {
+        LineStyleKind::Dashed
+    } else {
+        LineStyleKind::Normal
+    };
+
+    Arrow {
+        start: LineEndKind::None,
+        end,
+        line_style,
+        text: relation.caption(),
+        look: style,
+        src_port: None,
+        dst_port: None,
+    }
+}
+
+/// Counts the number of times drawn relations cross each other, treating each relation as a series of straight segments.
+fn count_crossings(arrows: &[Vec<Point>]) -> usize {
+    // The orientation of the triangle (a, b, c): positive if counterclockwise, negative if clockwise
+    fn turn(a: Point, b: Point, c: Point) -> f64 {
+        (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    }
+
+    // Segments which only touch (such as relations which share an item) don't count as crossing
+    fn crosses(a: (Point, Point), b: (Point, Point)) -> bool {
+        turn(a.0, a.1, b.0) * turn(a.0, a.1, b.1) < 0.
+            && turn(b.0, b.1, a.0) * turn(b.0, b.1, a.1) < 0.
+    }
+
+    let mut crossings = 0;
+
+    for (index, first) in arrows.iter().enumerate() {
+        for second in &arrows[index + 1..] {
+            for a in first.windows(2) {
+                for b in second.windows(2) {
+                    if crosses((a[0], a[1]), (b[0], b[1])) {
+                        crossings += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    crossings
+}
+
+fn node_style(color: Option<Color>) -> StyleAttr {
+    StyleAttr {
+        line_color: color.unwrap_or_else(|| Color::new(0x00_00_00_ff)),
+        line_width: LINE_WIDTH,
+        fill_color: Some(Color::new(0xff_ff_ff_ff)),
+        rounded: RADIUS,
+        font_size: 8,
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown error".to_string()
     }
 }
 
@@ -174,12 +678,21 @@ fn escape_string(x: &str) -> String {
     res
 }
 
+#[derive(Clone)]
 pub struct SVGWriter {
     content: String,
     view_size: Point,
     counter: usize,
     // A list of clip regions to generate.
     clip_regions: Vec<String>,
+    // The learner's progress through each item, if it should be displayed.
+    item_states: HashMap<Uuid, ItemState>,
+    // The renderings of nested Course Maps displayed within items.
+    clusters: HashMap<Uuid, Cluster>,
+    // The points which each drawn arrow passes through, used to compare layouts.
+    arrows: Vec<Vec<Point>>,
+    // Whether item labels are drawn as SVG text instead of HTML.
+    standalone: bool,
 }
 
 impl SVGWriter {
@@ -189,6 +702,10 @@ impl SVGWriter {
             view_size: Point::zero(),
             counter: 0,
             clip_regions: Vec::new(),
+            item_states: HashMap::new(),
+            clusters: HashMap::new(),
+            arrows: Vec::new(),
+            standalone: false,
         }
     }
 }
@@ -206,15 +723,82 @@ impl SVGWriter {
 
         let svg_line = format!(
             "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\
-            \" xmlns=\"http://www.w3.org/2000/svg\">\n<style>p{{padding:1em;margin:0}}div.course-map-item{{width:100%;height:100%;background-color: var(--mini-card-color);border-radius: {}px}}</style>\n",
-            self.view_size.x, self.view_size.y, self.view_size.x, self.view_size.y, RADIUS,
+            \" xmlns=\"http://www.w3.org/2000/svg\">\n",
+            self.view_size.x, self.view_size.y, self.view_size.x, self.view_size.y,
         );
         result.push_str(&svg_line);
+
+        // Standalone SVGs don't contain any HTML to style
+        if !self.standalone {
+            let style = format!(
+                "<style>p{{padding:1em;margin:0}}div.course-map-item{{width:100%;height:100%;background-color: var(--mini-card-color);border-radius: {RADIUS}px}}\
+            div.course-map-item-locked{{opacity:0.6}}div.course-map-progress{{height:0.4em;margin:0 1em;border-radius:0.2em;background-color:rgba(0,0,0,0.15)}}\
+            div.course-map-progress>div{{height:100%;border-radius:0.2em;background-color:var(--chapter-progress-color, #a33)}}</style>\n"
+            );
+            result.push_str(&style);
+        }
+
         result.push_str(SVG_DEFS);
         result.push_str(&self.content);
         result.push_str(SVG_FOOTER);
         result
     }
+
+    // The font-family attribute of text which isn't an item label.
+    fn font_family(&self) -> String {
+        if self.standalone {
+            format!(" font-family=\"{STANDALONE_FONT_FAMILY}\"")
+        } else {
+            String::new()
+        }
+    }
+
+    // Draw an item's label as SVG text centered on \p center, wrapping it
+    // between words to fit within \p width.
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_sign_loss)]
+    fn draw_label(&mut self, center: Point, width: f64, label: &str) {
+        // Based on the average width of a character in a sans-serif font
+        let max_chars = ((width / LABEL_FONT_SIZE - 2.) / 0.55).max(1.) as usize;
+
+        let mut lines: Vec<String> = Vec::new();
+
+        for word in label.split_whitespace() {
+            match lines.last_mut() {
+                Some(line) if line.chars().count() + word.chars().count() < max_chars => {
+                    line.push(' ');
+                    line.push_str(word);
+                }
+                _ => lines.push(word.to_string()),
+            }
+        }
+
+        let mut content = String::new();
+
+        for (index, line) in lines.iter().enumerate() {
+            let offset = if index == 0 {
+                0.6 * (1. - lines.len() as f64)
+            } else {
+                1.2
+            };
+
+            let tspan = format!(
+                "<tspan x=\"{}\" dy=\"{}em\">{}</tspan>",
+                center.x,
+                offset,
+                escape_string(line)
+            );
+            content.push_str(&tspan);
+        }
+
+        let line = format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"{STANDALONE_FONT_FAMILY}\" font-size=\"{}\" \
+            text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+            center.x, center.y, LABEL_FONT_SIZE, content
+        );
+        self.content.push_str(&line);
+    }
 }
 impl RenderBackend for SVGWriter {
     fn draw_rect(&mut self, xy: Point, size: Point, look: &StyleAttr, clip: Option<ClipHandle>) {
@@ -267,18 +851,74 @@ impl RenderBackend for SVGWriter {
 
     fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr) {
         if let Ok(course) = from_str::<CourseMapItem>(text) {
-            let width = SIZE * RATIO;
-            let height = SIZE / RATIO;
+            let size = item_size(&course, &self.clusters);
+            let origin = Point::new(xy.x - (size.x / 2.), xy.y - (size.y / 2.));
+            let mut height = size.y;
+
+            let mut classes = String::new();
+            let mut markers = String::new();
+            let mut overlay = String::new();
+
+            if let Some(state) = self.item_states.get(&course.uuid) {
+                if state.locked {
+                    classes.push_str(" course-map-item-locked");
+                    markers.push_str(" 🔒");
+                }
+
+                if let Some(completion) = state.completion {
+                    if completion >= 1.0 {
+                        classes.push_str(" course-map-item-completed");
+                        markers.push_str(" 🏆");
+                    }
+
+                    overlay = format!(
+                        "<div class=\"course-map-progress\"><div style=\"width:{}%\"></div></div>",
+                        (completion.clamp(0.0, 1.0) * 100.0).round()
+                    );
+                }
+            }
+
+            // Nested Course Maps are displayed below the item's label
+            if let Some(cluster) = self.clusters.get(&course.uuid) {
+                height = CLUSTER_HEADER;
+
+                classes.push_str(" course-map-cluster");
+
+                let nested = format!(
+                    "<svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" overflow=\"visible\">\n{}</svg>\n",
+                    origin.x + (size.x - cluster.size.x) / 2.,
+                    origin.y + CLUSTER_HEADER,
+                    cluster.size.x,
+                    cluster.size.y,
+                    cluster.size.x,
+                    cluster.size.y,
+                    cluster.content,
+                );
+                self.content.push_str(&nested);
+            }
+
+            if self.standalone {
+                self.draw_label(
+                    Point::new(origin.x + size.x / 2., origin.y + height / 2.),
+                    size.x,
+                    &course.label,
+                );
+
+                return;
+            }
 
             #[allow(clippy::cast_precision_loss)]
             self.content.push_str(&format!(
-                "<foreignObject x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"><div class=\"course-map-item course-map-item-{}\"><p>{}</p></div></foreignObject>\n",
-                xy.x - (width / 2.) + (LINE_WIDTH as f64 / 2.),
-                xy.y - (height / 2.) + (LINE_WIDTH as f64 / 2.),
-                width - LINE_WIDTH as f64,
+                "<foreignObject x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"><div class=\"course-map-item course-map-item-{}{}\"><p>{}{}</p>{}</div></foreignObject>\n",
+                origin.x + (LINE_WIDTH as f64 / 2.),
+                origin.y + (LINE_WIDTH as f64 / 2.),
+                size.x - LINE_WIDTH as f64,
                 height - LINE_WIDTH as f64,
 				course.uuid,
+                classes,
                 course.label,
+                markers,
+                overlay,
             ));
 
             return;
@@ -299,8 +939,10 @@ impl RenderBackend for SVGWriter {
         #[allow(clippy::cast_precision_loss)]
         self.grow_window(xy, Point::new(10., len as f64 * 10.));
         let line = format!(
-            "<text dominant-baseline=\"middle\" text-anchor=\"middle\"
+            "<text dominant-baseline=\"middle\" text-anchor=\"middle\" font-size=\"{}\"{}
             x=\"{}\" y=\"{}\">{}</text>",
+            look.font_size,
+            self.font_family(),
             xy.x,
             xy.y - size_y / 2.,
             &content
@@ -328,6 +970,14 @@ impl RenderBackend for SVGWriter {
             self.grow_window(point.1, Point::zero());
         }
 
+        self.arrows.push(
+            path.iter()
+                .take(1)
+                .map(|point| point.0)
+                .chain(path.iter().skip(1).map(|point| point.1))
+                .collect(),
+        );
+
         let dash = if dashed {
             &"stroke-dasharray=\"5,5\""
         } else {
@@ -385,8 +1035,11 @@ impl RenderBackend for SVGWriter {
         self.content.push_str(&line);
 
         let line = format!(
-            "<text><textPath href=\"#arrow{}\" startOffset=\"50%\" \
+            "<text font-size=\"{}\"{} fill=\"{}\" dy=\"-0.4em\"><textPath href=\"#arrow{}\" startOffset=\"50%\" \
             text-anchor=\"middle\">{}</textPath></text>",
+            look.font_size,
+            self.font_family(),
+            stroke_color.to_web_color(),
             self.counter,
             escape_string(text)
         );
@@ -425,3 +1078,37 @@ impl RenderBackend for SVGWriter {
         handle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CourseMap, SVGWriter};
+
+    #[test]
+    fn layered_layout_skips_self_relations() {
+        let course_map: CourseMap = toml::from_str(
+            r#"
+title = "Course Map"
+layout = "Layered"
+
+[[courses]]
+uuid = "0a0a0a0a-0a0a-0a0a-0a0a-0a0a0a0a0a0a"
+label = "First"
+
+[[courses]]
+uuid = "0b0b0b0b-0b0b-0b0b-0b0b-0b0b0b0b0b0b"
+label = "Second"
+relations = [
+    { uuid = "0b0b0b0b-0b0b-0b0b-0b0b-0b0b0b0b0b0b", type = "Layout" },
+    { uuid = "0a0a0a0a-0a0a-0a0a-0a0a-0a0a0a0a0a0a", type = "Prerequisite" },
+]
+"#,
+        )
+        .unwrap();
+
+        let mut writer = SVGWriter::new();
+        course_map.draw(&mut writer, None);
+
+        // The invisible self-relation must not take the place of the visible relation
+        assert_eq!(writer.arrows.len(), 1);
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    env,
+    fs::{self, File},
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+use zip::ZipWriter;
+
+/// An empty folder within the system's temporary folder, which is removed (along with its contents) when dropped
+pub(super) struct TemporaryFolder(PathBuf);
+
+impl TemporaryFolder {
+    pub(super) fn new() -> Self {
+        let path = env::temp_dir().join(format!("portal-test-{}", Uuid::new_v4()));
+        fs::create_dir(&path).unwrap();
+
+        Self(path)
+    }
+}
+
+impl Deref for TemporaryFolder {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TemporaryFolder {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes an archive named `name` to `folder`, adding its entries using `build`.
+pub(super) fn write_archive(
+    folder: &Path,
+    name: &str,
+    build: impl FnOnce(&mut ZipWriter<File>),
+) -> PathBuf {
+    let path = folder.join(name);
+    let mut writer = ZipWriter::new(File::create(&path).unwrap());
+
+    build(&mut writer);
+
+    writer.finish().unwrap();
+
+    path
+}
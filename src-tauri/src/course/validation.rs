@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{
+    epub::{Package, TocEntry},
+    Course, CourseMap, CourseMapRelationType,
+};
+
+/// A problem found while checking a ``Course`` against the Tables of Contents of its textbooks
+#[derive(Serialize, Debug)]
+#[serde(tag = "type")]
+pub enum CourseDiagnostic {
+    /// The textbook could not be opened, or its Table of Contents could not be read
+    UnreadableTextbook { book: usize, cause: String },
+    /// A chapter's root href does not exist in the textbook's Table of Contents, so the chapter can never be completed through it
+    UnknownRoot {
+        book: usize,
+        chapter: usize,
+        href: String,
+    },
+    /// A section href does not exist in the textbook's Table of Contents, so it can never be completed
+    UnknownSection {
+        book: usize,
+        chapter: usize,
+        group: usize,
+        href: String,
+    },
+    /// A Table of Contents entry is not included in any chapter of the textbook
+    UncoveredEntry {
+        book: usize,
+        href: String,
+        label: String,
+    },
+    /// A textbook's progress key (its id, or its position if it has no id) is also used by an earlier textbook, so their progress is combined
+    ConflictingTextbookId { book: usize, id: String },
+    /// A table of migrations is keyed by a progress key which isn't used by any textbook, so its migrations are ignored
+    UnknownMigrationTextbook { key: String },
+    /// A migration's old href is still used by its textbook, so the migration is ignored
+    IgnoredMigration { book: usize, from: String },
+    /// A migration's new href isn't used by its textbook, so migrated progress isn't counted
+    UnknownMigrationTarget {
+        book: usize,
+        from: String,
+        to: String,
+    },
+}
+
+impl Course {
+    /// Checks every chapter root and section href against the Table of Contents of its textbook, and checks that textbook ids are unique and migrations are usable.
+    ///
+    /// Textbook paths must already be resolved, as they are when loaded through ``DataStore``.
+    pub fn validate(&self) -> Vec<CourseDiagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let mut keys = HashSet::with_capacity(self.books.len());
+
+        for (book_index, book) in self.books.iter().enumerate() {
+            let key = book.progress_key(book_index);
+
+            if !keys.insert(key.clone()) {
+                diagnostics.push(CourseDiagnostic::ConflictingTextbookId {
+                    book: book_index,
+                    id: key,
+                });
+            }
+        }
+
+        for (key, migrations) in &self.migrations {
+            let textbook = self
+                .books
+                .iter()
+                .enumerate()
+                .find(|(book_index, book)| book.progress_key(*book_index) == *key);
+
+            let Some((book_index, book)) = textbook else {
+                diagnostics.push(CourseDiagnostic::UnknownMigrationTextbook { key: key.clone() });
+                continue;
+            };
+
+            let hrefs = book.hrefs();
+
+            for (from, to) in migrations {
+                if hrefs.contains(from.as_str()) {
+                    diagnostics.push(CourseDiagnostic::IgnoredMigration {
+                        book: book_index,
+                        from: from.clone(),
+                    });
+                } else if !hrefs.contains(to.as_str()) {
+                    diagnostics.push(CourseDiagnostic::UnknownMigrationTarget {
+                        book: book_index,
+                        from: from.clone(),
+                        to: to.clone(),
+                    });
+                }
+            }
+        }
+
+        for (book_index, book) in self.books.iter().enumerate() {
+            let package = match Package::open(&book.file) {
+                Ok(package) => package,
+                Err(error) => {
+                    diagnostics.push(CourseDiagnostic::UnreadableTextbook {
+                        book: book_index,
+                        cause: format!("{error}"),
+                    });
+                    continue;
+                }
+            };
+
+            let entries = TocEntry::flatten(&package.toc);
+            let toc: HashSet<&str> = entries.iter().filter_map(|e| e.href.as_deref()).collect();
+
+            let mut covered = HashSet::new();
+
+            for (chapter_index, chapter) in book.chapters.iter().enumerate() {
+                if let Some(root) = &chapter.root {
+                    if !toc.contains(root.as_str()) {
+                        diagnostics.push(CourseDiagnostic::UnknownRoot {
+                            book: book_index,
+                            chapter: chapter_index,
+                            href: root.clone(),
+                        });
+                    }
+
+                    covered.insert(root.as_str());
+                }
+
+                for (group_index, group) in chapter.groups.iter().enumerate() {
+                    for section in &group.sections {
+                        if !toc.contains(section.as_str()) {
+                            diagnostics.push(CourseDiagnostic::UnknownSection {
+                                book: book_index,
+                                chapter: chapter_index,
+                                group: group_index,
+                                href: section.clone(),
+                            });
+                        }
+
+                        covered.insert(section.as_str());
+                    }
+                }
+            }
+
+            if book.chapters.is_empty() {
+                continue;
+            }
+
+            for entry in entries {
+                if let Some(href) = &entry.href {
+                    if !covered.contains(href.as_str()) {
+                        diagnostics.push(CourseDiagnostic::UncoveredEntry {
+                            book: book_index,
+                            href: href.clone(),
+                            label: entry.label.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// A problem found while checking the items and relations of a ``CourseMap``
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum CourseMapDiagnostic {
+    /// More than one item has the same UUID, so only the last one is displayed
+    DuplicateItem { item: Uuid },
+    /// A relation's source is not an item in the Course Map, so the relation is not displayed
+    UnknownRelationTarget { item: Uuid, relation: Uuid },
+    /// An item has a relation to itself
+    SelfRelation { item: Uuid },
+    /// A chain of prerequisites which leads back to its first item, so none of the items in it can be started
+    ///
+    /// Each item in the path is a prerequisite of the next, and the last item is a prerequisite of the first.
+    PrerequisiteCycle { path: Vec<Uuid> },
+    /// A chain of nested Course Maps which leads back to its first Course Map, so the item which completes it is displayed as Collapsed
+    ///
+    /// Each Course Map in the path includes the next, and the last Course Map includes the first.
+    NestedCycle { path: Vec<Uuid> },
+    /// An item references a Course Map which couldn't be loaded, so it is displayed as Collapsed
+    UnreadableNestedMap { item: Uuid, cause: String },
+}
+
+impl CourseMap {
+    /// Checks for duplicate items, relations which can't be displayed, and prerequisite cycles.
+    pub fn validate(&self) -> Vec<CourseMapDiagnostic> {
+        let mut diagnostics = Vec::new();
+
+        let mut items = HashSet::with_capacity(self.courses.len());
+        let mut duplicates = HashSet::new();
+
+        for item in &self.courses {
+            if !items.insert(item.uuid) && duplicates.insert(item.uuid) {
+                diagnostics.push(CourseMapDiagnostic::DuplicateItem { item: item.uuid });
+            }
+        }
+
+        // Maps each item to the items it is a prerequisite of
+        let mut prerequisites: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+
+        for item in &self.courses {
+            for relation in &item.relations {
+                if relation.uuid == item.uuid {
+                    diagnostics.push(CourseMapDiagnostic::SelfRelation { item: item.uuid });
+                } else if !items.contains(&relation.uuid) {
+                    diagnostics.push(CourseMapDiagnostic::UnknownRelationTarget {
+                        item: item.uuid,
+                        relation: relation.uuid,
+                    });
+                } else if relation.r#type == CourseMapRelationType::Prerequisite {
+                    prerequisites
+                        .entry(relation.uuid)
+                        .or_default()
+                        .push(item.uuid);
+                }
+            }
+        }
+
+        for path in find_cycles(
+            &self.courses.iter().map(|i| i.uuid).collect::<Vec<_>>(),
+            &prerequisites,
+        ) {
+            diagnostics.push(CourseMapDiagnostic::PrerequisiteCycle { path });
+        }
+
+        diagnostics
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VisitState {
+    InProgress,
+    Finished,
+}
+
+/// Finds cycles in a directed graph using a depth-first search, returning one cycle for each edge which closes a cycle.
+fn find_cycles(nodes: &[Uuid], edges: &HashMap<Uuid, Vec<Uuid>>) -> Vec<Vec<Uuid>> {
+    let mut cycles = Vec::new();
+    let mut states: HashMap<Uuid, VisitState> = HashMap::with_capacity(nodes.len());
+
+    for node in nodes {
+        if states.contains_key(node) {
+            continue;
+        }
+
+        // Each stack entry is a node, along with the index of the next edge to follow
+        let mut stack = vec![(*node, 0)];
+        states.insert(*node, VisitState::InProgress);
+
+        while let Some((current, index)) = stack.last_mut() {
+            let current = *current;
+            let next = edges.get(&current).and_then(|e| e.get(*index)).copied();
+            *index += 1;
+
+            let Some(next) = next else {
+                states.insert(current, VisitState::Finished);
+                stack.pop();
+                continue;
+            };
+
+            match states.get(&next) {
+                None => {
+                    states.insert(next, VisitState::InProgress);
+                    stack.push((next, 0));
+                }
+                Some(VisitState::InProgress) => {
+                    let start = stack.iter().position(|(n, _)| *n == next).unwrap_or(0);
+
+                    cycles.push(stack[start..].iter().map(|(n, _)| *n).collect());
+                }
+                Some(VisitState::Finished) => {}
+            }
+        }
+    }
+
+    cycles
+}
@@ -6,6 +6,8 @@ mod api;
 mod course;
 mod progress;
 
+pub use api::cli::run as run_cli;
+
 #[allow(clippy::missing_panics_doc)]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -35,21 +37,41 @@ pub fn run() {
                     .expect("Unable to find application data directory"),
             ));
 
+            api::watcher::start(app.handle().clone());
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             api::get_data_dir,
             api::get_internal_data_dir,
+            api::get_export_dir,
             api::get_backend_date,
             api::get_course,
+            api::validate_course,
+            api::export_course,
             api::set_course_completion,
+            api::get_annotations,
+            api::create_annotation,
+            api::update_annotation,
+            api::delete_annotation,
+            api::export_annotations,
             api::get_active_courses,
             api::set_active_courses,
             api::get_all,
             api::get_active,
+            api::get_recommended,
+            api::search_textbooks,
+            api::get_course_map_progress,
             api::get_overall_progress,
             api::get_settings,
             api::set_settings,
+            api::get_profiles,
+            api::create_profile,
+            api::switch_profile,
+            api::rename_profile,
+            api::delete_profile,
+            api::export_progress,
+            api::import_progress,
         ])
         .plugin(tauri_plugin_shell::init())
         .run(tauri::generate_context!())
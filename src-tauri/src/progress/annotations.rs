@@ -0,0 +1,275 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::super::course::{
+    outline::{self, TextbookOutline},
+    Course,
+};
+
+/// The kind of an ``Annotation``
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationKind {
+    /// A range of highlighted text, which may have a note attached
+    Highlight,
+    /// A note attached to a location within a textbook
+    Note,
+    /// A saved location within a textbook
+    Bookmark,
+}
+
+/// A highlight, note, or bookmark within a textbook
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Annotation {
+    /// Assigned when the annotation is created.
+    #[serde(default)]
+    pub uuid: Uuid,
+    pub kind: AnnotationKind,
+    /// The ``Textbook::progress_key`` of the textbook containing the annotation.
+    pub book: String,
+    /// The href of the content document containing the annotation, relative to the textbook's package document.
+    pub href: String,
+    /// The location of the annotation as an EPUB CFI. Highlights use a CFI range covering the highlighted text.
+    pub cfi: String,
+    /// The color of a highlight, as a CSS color.
+    pub color: Option<String>,
+    /// The highlighted text, or the text at the annotation's location.
+    pub excerpt: Option<String>,
+    /// The learner's notes.
+    pub note: Option<String>,
+    /// Assigned when the annotation is created.
+    #[serde(default)]
+    pub created: DateTime<Utc>,
+    /// Updated whenever the annotation is changed.
+    #[serde(default)]
+    pub modified: DateTime<Utc>,
+}
+
+impl Annotation {
+    /// Moves an annotation stored using its textbook's position to the textbook's id, if the textbook has been given an id since the annotation was created.
+    ///
+    /// Returns true if the annotation was moved.
+    pub fn resolve_textbook_id(&mut self, course: &Course) -> bool {
+        let Ok(book_index) = self.book.parse::<usize>() else {
+            return false;
+        };
+
+        match course
+            .books
+            .get(book_index)
+            .and_then(|book| book.id.as_ref())
+        {
+            Some(id) if *id != self.book => {
+                self.book.clone_from(id);
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Moves an annotation into the content document which replaces its own, using its textbook's migrations.
+    ///
+    /// A migration applies when its old href is within the annotation's content document, and no href still used by the textbook is. Returns true if the annotation was moved.
+    pub fn apply_migrations(&mut self, course: &Course) -> bool {
+        let Some(migrations) = course.migrations.get(&self.book) else {
+            return false;
+        };
+
+        let Some(book) = course
+            .books
+            .iter()
+            .enumerate()
+            .find_map(|(index, book)| (book.progress_key(index) == self.book).then_some(book))
+        else {
+            return false;
+        };
+
+        if book
+            .hrefs()
+            .into_iter()
+            .any(|href| outline::document_href(href) == self.href)
+        {
+            return false;
+        }
+
+        let replacement = migrations
+            .iter()
+            .filter(|(old, _)| outline::document_href(old) == self.href)
+            .map(|(_, new)| outline::document_href(new))
+            .find(|new| *new != self.href);
+
+        match replacement {
+            Some(new) => {
+                self.href = new.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Formats a Course's annotations as a Markdown document, grouped by textbook and by chapter in reading order.
+///
+/// Every textbook in the Course is read to locate its chapters. Annotations outside of any chapter, or in textbooks which are no longer part of the Course, are listed separately at the end of each textbook and the end of the document.
+pub fn to_markdown(course: &Course, annotations: &[Annotation]) -> String {
+    let mut markdown = String::new();
+
+    let _ = writeln!(markdown, "# {}", course.title);
+
+    for (book_index, book) in course.books.iter().enumerate() {
+        let key = book.progress_key(book_index);
+
+        let mut book_annotations: Vec<_> = annotations.iter().filter(|a| a.book == key).collect();
+
+        if book_annotations.is_empty() {
+            continue;
+        }
+
+        let outline = book.outline();
+
+        book_annotations.sort_by_key(|annotation| {
+            (
+                outline.chapter(&annotation.href).unwrap_or(usize::MAX),
+                outline.position(&annotation.href).unwrap_or(usize::MAX),
+                annotation.created,
+            )
+        });
+
+        let _ = writeln!(markdown, "\n## {}", book.label);
+
+        let mut current_chapter = None;
+
+        for annotation in book_annotations {
+            let chapter = outline.chapter(&annotation.href);
+
+            if current_chapter != Some(chapter) {
+                let _ = writeln!(markdown, "\n### {}", chapter_title(&outline, chapter));
+
+                current_chapter = Some(chapter);
+            }
+
+            write_annotation(&mut markdown, annotation);
+        }
+    }
+
+    let keys: Vec<_> = course
+        .books
+        .iter()
+        .enumerate()
+        .map(|(index, book)| book.progress_key(index))
+        .collect();
+
+    let mut removed: Vec<_> = annotations
+        .iter()
+        .filter(|annotation| !keys.contains(&annotation.book))
+        .collect();
+
+    if !removed.is_empty() {
+        removed.sort_by_key(|annotation| annotation.created);
+
+        let _ = writeln!(markdown, "\n## Other Textbooks");
+
+        for annotation in removed {
+            write_annotation(&mut markdown, annotation);
+        }
+    }
+
+    markdown
+}
+
+fn chapter_title(outline: &TextbookOutline, chapter: Option<usize>) -> String {
+    match chapter {
+        Some(chapter) => outline
+            .label(chapter)
+            .map_or_else(|| format!("Chapter {}", chapter + 1), str::to_string),
+        None => "Other Annotations".to_string(),
+    }
+}
+
+fn write_annotation(markdown: &mut String, annotation: &Annotation) {
+    let kind = match annotation.kind {
+        AnnotationKind::Highlight => "🖍️ Highlight",
+        AnnotationKind::Note => "📝 Note",
+        AnnotationKind::Bookmark => "🔖 Bookmark",
+    };
+
+    let _ = writeln!(
+        markdown,
+        "\n**{kind}** · {}",
+        annotation.created.with_timezone(&Local).date_naive()
+    );
+
+    if let Some(excerpt) = annotation
+        .excerpt
+        .as_deref()
+        .filter(|e| !e.trim().is_empty())
+    {
+        markdown.push('\n');
+
+        for line in excerpt.trim().lines() {
+            let _ = writeln!(markdown, "> {line}");
+        }
+    }
+
+    if let Some(note) = annotation.note.as_deref().filter(|n| !n.trim().is_empty()) {
+        let _ = writeln!(markdown, "\n{}", note.trim());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Annotation, AnnotationKind};
+    use crate::course::Course;
+
+    fn bookmark(book: &str, href: &str) -> Annotation {
+        Annotation {
+            uuid: uuid::Uuid::new_v4(),
+            kind: AnnotationKind::Bookmark,
+            book: book.to_string(),
+            href: href.to_string(),
+            cfi: String::new(),
+            color: None,
+            excerpt: None,
+            note: None,
+            created: chrono::Utc::now(),
+            modified: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn migrations_are_scoped_to_their_textbook() {
+        let course: Course = toml::from_str(
+            r#"
+title = "Course"
+
+[[books]]
+id = "algebra"
+label = "Algebra"
+file = "algebra"
+chapters = [{ groups = [{ sections = ["intro.xhtml#start"] }] }]
+
+[[books]]
+id = "geometry"
+label = "Geometry"
+file = "geometry"
+chapters = [{ groups = [{ sections = ["start.xhtml#start"] }] }]
+
+[migrations.geometry]
+"intro.xhtml#start" = "start.xhtml#start"
+"#,
+        )
+        .unwrap();
+
+        let mut algebra = bookmark("algebra", "intro.xhtml");
+        let mut geometry = bookmark("geometry", "intro.xhtml");
+
+        assert!(!algebra.apply_migrations(&course));
+        assert_eq!(algebra.href, "intro.xhtml");
+
+        // Algebra still uses intro.xhtml, which doesn't stop Geometry's migration of it
+        assert!(geometry.apply_migrations(&course));
+        assert_eq!(geometry.href, "start.xhtml");
+        assert!(!geometry.apply_migrations(&course));
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{annotations::Annotation, CourseCompletion, OverallProgress};
+
+/// The version of the progress backup format written by this version of the application
+pub const BACKUP_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error("Backup version {0} is not supported by this version of the application")]
+    UnsupportedVersion(u32),
+}
+
+/// A portable copy of all progress stored in a ``Database``
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProgressBackup {
+    /// The version of the backup format, which is checked before the rest of the backup is parsed.
+    pub version: u32,
+    /// The time at which the backup was created.
+    pub created: DateTime<Utc>,
+    /// The raw completion data of every ``Course`` with progress, by UUID.
+    pub courses: HashMap<Uuid, CourseCompletion>,
+    /// The fingerprint of the ``Course`` which each Course's completion was last stored for, by UUID.
+    ///
+    /// Backups created before fingerprints were stored don't include this, in which case Course migrations are checked when each Course is next loaded.
+    #[serde(default)]
+    pub fingerprints: HashMap<Uuid, String>,
+    /// The highlights, notes, and bookmarks within each Course, by UUID.
+    ///
+    /// Imported annotations are checked against Course migrations when each Course's annotations are next read.
+    #[serde(default)]
+    pub annotations: HashMap<Uuid, Vec<Annotation>>,
+    pub overall: OverallProgress,
+    pub active_courses: Vec<Uuid>,
+    /// The frontend's settings, which are stored as an opaque string.
+    pub settings: String,
+}
+
+/// Determines how an imported ``ProgressBackup`` is combined with existing progress
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Combines imported progress with existing progress, keeping everything which is present in either.
+    Merge,
+    /// Discards all existing progress, replacing it with the imported progress.
+    Replace,
+}
+
+#[derive(Deserialize)]
+struct BackupHeader {
+    version: u32,
+}
+
+impl ProgressBackup {
+    pub fn new(
+        courses: HashMap<Uuid, CourseCompletion>,
+        fingerprints: HashMap<Uuid, String>,
+        annotations: HashMap<Uuid, Vec<Annotation>>,
+        overall: OverallProgress,
+        active_courses: Vec<Uuid>,
+        settings: String,
+    ) -> Self {
+        Self {
+            version: BACKUP_VERSION,
+            created: Utc::now(),
+            courses,
+            fingerprints,
+            annotations,
+            overall,
+            active_courses,
+            settings,
+        }
+    }
+    /// Parses a backup, returning an error if it was created by a newer version of the application.
+    pub fn from_json(data: &str) -> Result<Self, BackupError> {
+        let header: BackupHeader = serde_json::from_str(data)?;
+
+        if header.version > BACKUP_VERSION {
+            return Err(BackupError::UnsupportedVersion(header.version));
+        }
+
+        Ok(serde_json::from_str(data)?)
+    }
+    pub fn to_json(&self) -> Result<String, BackupError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    /// Combines another backup with this one, as described by ``ImportMode::Merge``.
+    ///
+    /// Settings are only taken from the other backup if this backup has none. If the completion of a Course was stored for different versions of the Course, its fingerprint is discarded, so that Course migrations are checked when it is next loaded. Annotations present in both backups keep whichever version was modified most recently.
+    pub fn merge(&mut self, mut other: Self) {
+        for (uuid, completion) in other.courses {
+            let fingerprint = other.fingerprints.remove(&uuid);
+
+            match self.courses.entry(uuid) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().merge(completion);
+
+                    if self.fingerprints.get(&uuid) != fingerprint.as_ref() {
+                        self.fingerprints.remove(&uuid);
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(completion);
+
+                    match fingerprint {
+                        Some(fingerprint) => self.fingerprints.insert(uuid, fingerprint),
+                        None => self.fingerprints.remove(&uuid),
+                    };
+                }
+            }
+        }
+
+        for (uuid, annotations) in other.annotations {
+            let existing = self.annotations.entry(uuid).or_default();
+
+            for annotation in annotations {
+                match existing.iter_mut().find(|a| a.uuid == annotation.uuid) {
+                    Some(current) => {
+                        if annotation.modified > current.modified {
+                            *current = annotation;
+                        }
+                    }
+                    None => existing.push(annotation),
+                }
+            }
+        }
+
+        self.overall.merge(other.overall);
+
+        for uuid in other.active_courses {
+            if !self.active_courses.contains(&uuid) {
+                self.active_courses.push(uuid);
+            }
+        }
+
+        if self.settings.is_empty() {
+            self.settings = other.settings;
+        }
+    }
+}
+
+impl CourseCompletion {
+    /// Combines completion data, keeping every completed section and the largest amount of time spent on each day.
+    ///
+    /// The existing position within each textbook is kept if one is present.
+    fn merge(&mut self, other: Self) {
+        for (date, time) in other.time_spent {
+            let entry = self.time_spent.entry(date).or_default();
+            *entry = (*entry).max(time);
+        }
+
+        for (key, book) in other.books {
+            match self.books.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    let existing = entry.get_mut();
+
+                    existing.completed_sections.extend(book.completed_sections);
+
+                    if existing.position.is_none() {
+                        existing.position = book.position;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(book);
+                }
+            }
+        }
+    }
+}
+
+impl OverallProgress {
+    /// Combines overall progress, keeping the largest value recorded for each day.
+    ///
+    /// Progress made on the same day on separate devices isn't added together, as the same progress may have been recorded on both devices.
+    fn merge(&mut self, other: Self) {
+        for (date, chapters) in other.chapters_completed {
+            let entry = self.chapters_completed.entry(date).or_default();
+            *entry = entry.max(chapters);
+        }
+
+        for (date, time) in other.time_spent {
+            let entry = self.time_spent.entry(date).or_default();
+            *entry = (*entry).max(time);
+        }
+    }
+}
@@ -1,14 +1,21 @@
-use std::{ops::Deref, path::Path};
+use std::{collections::HashMap, ops::Deref, path::Path};
 
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sled::{
-    transaction::{ConflictableTransactionError, TransactionError},
-    Config, Db,
+    transaction::{ConflictableTransactionError, TransactionError, Transactional},
+    Config, Db, Tree,
 };
 use thiserror::Error;
 use tokio::task::{self, JoinError};
 use uuid::Uuid;
 
-use super::{super::course::Course, CourseCompletion, CourseProgress, OverallProgress};
+use super::{
+    super::course::Course,
+    annotations::Annotation,
+    backup::{ImportMode, ProgressBackup},
+    migration, CourseCompletion, CourseProgress, OverallProgress,
+};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -18,12 +25,18 @@ pub enum Error {
     Encoding(#[from] bincode::Error),
     #[error("Task was terminated or panicked")]
     BlockingTaskFailed(#[from] JoinError),
+    #[error("Database was created by a newer version of the application (schema version {0})")]
+    UnsupportedSchemaVersion(u32),
+    #[error("Record was created by a newer version of the application (record version {0})")]
+    UnsupportedRecordVersion(u32),
+    #[error("Annotation {0} does not exist")]
+    UnknownAnnotation(Uuid),
 }
 
-impl From<TransactionError<bincode::Error>> for Error {
-    fn from(value: TransactionError<bincode::Error>) -> Self {
+impl From<TransactionError<Error>> for Error {
+    fn from(value: TransactionError<Error>) -> Self {
         match value {
-            TransactionError::Abort(abort) => Self::Encoding(abort),
+            TransactionError::Abort(abort) => abort,
             TransactionError::Storage(error) => Self::Storage(error),
         }
     }
@@ -34,85 +47,196 @@ pub struct Database {
     root: Db,
 }
 
-const SETTINGS_KEY: &[u8] = b"frontend_settings";
-const ACTIVE_COURSES_KEY: &[u8] = b"active_courses";
-const PROGRESS_TREE_KEY: &[u8] = b"course_progress";
-const OVERALL_PROGRESS_KEY: &[u8] = b"overall"; // Must have a length not equal to 16 bytes.
+pub(super) const SETTINGS_KEY: &[u8] = b"frontend_settings";
+pub(super) const ACTIVE_COURSES_KEY: &[u8] = b"active_courses";
+pub(super) const PROGRESS_TREE_KEY: &[u8] = b"course_progress";
+pub(super) const OVERALL_PROGRESS_KEY: &[u8] = b"overall"; // Must have a length not equal to 16 bytes.
+const FINGERPRINT_TREE_KEY: &[u8] = b"course_fingerprints";
+/// Annotations are keyed by Course UUID followed by annotation UUID, so that a Course's annotations can be read with a prefix scan.
+const ANNOTATION_TREE_KEY: &[u8] = b"annotations";
+/// The fingerprint of the ``Course`` which each Course's annotations were last migrated for, by Course UUID.
+const ANNOTATION_FINGERPRINT_TREE_KEY: &[u8] = b"annotation_fingerprints";
+
+/// A type which is stored in the database
+///
+/// ``Record::VERSION`` must be incremented whenever the type's encoding changes, along with adding a migration which upgrades existing records.
+pub(super) trait Record: Serialize + DeserializeOwned {
+    const VERSION: u32;
+}
+
+impl Record for CourseCompletion {
+    const VERSION: u32 = 2;
+}
+
+impl Record for OverallProgress {
+    const VERSION: u32 = 1;
+}
+
+impl Record for Annotation {
+    const VERSION: u32 = 1;
+}
+
+/// The list of active Courses
+impl Record for Vec<Uuid> {
+    const VERSION: u32 = 1;
+}
+
+/// The frontend's settings, and Course fingerprints
+impl Record for String {
+    const VERSION: u32 = 1;
+}
+
+/// A stored record, tagged with the version of its type
+#[derive(Serialize, Deserialize)]
+pub(super) struct Envelope<T> {
+    pub(super) version: u32,
+    pub(super) value: T,
+}
+
+/// Encodes a record within an ``Envelope``.
+pub(super) fn encode<T: Record>(value: &T) -> Result<Vec<u8>, Error> {
+    Ok(bincode::serialize(&Envelope {
+        version: T::VERSION,
+        value,
+    })?)
+}
+
+/// Decodes a record, returning an error if it doesn't have the current version of its type.
+pub(super) fn decode<T: Record>(data: &[u8]) -> Result<T, Error> {
+    // Trailing bytes are allowed, so only the version is read
+    let version: u32 = bincode::deserialize(data)?;
+
+    if version != T::VERSION {
+        return Err(Error::UnsupportedRecordVersion(version));
+    }
+
+    Ok(bincode::deserialize::<Envelope<T>>(data)?.value)
+}
 
 impl Database {
     // Application initialization is done before an async runtime is initalized
     pub fn new(root: &Path) -> Result<Database, Error> {
         let config = Config::new().path(root);
 
-        Ok(Database {
-            root: /*task::spawn_blocking(move || */config.open()/*).await?*/?,
-        })
+        Self::open(
+            /*task::spawn_blocking(move || */ config.open()/*).await?*/?,
+        )
+    }
+    /// Writes all changes to disk and closes this handle to the database.
+    ///
+    /// The database itself is only closed once every clone of it has been closed or dropped.
+    pub async fn close(self) -> Result<(), Error> {
+        self.root.flush_async().await?;
+
+        Ok(())
+    }
+    /// Wraps an open database, upgrading it to the current schema version if needed.
+    fn open(root: Db) -> Result<Database, Error> {
+        migration::migrate(&root)?;
+
+        Ok(Database { root })
     }
     pub async fn get_course_progress(
         &self,
         course: Course,
     ) -> Result<(Course, CourseCompletion, CourseProgress), Error> {
         let progress_tree = self.root.open_tree(PROGRESS_TREE_KEY)?;
+        let fingerprint_tree = self.root.open_tree(FINGERPRINT_TREE_KEY)?;
 
         task::spawn_blocking(move || {
             let uuid = course.uuid.unwrap();
 
-            let completion = if let Some(data) = progress_tree.get(uuid.as_bytes())? {
-                bincode::deserialize(&data)?
+            let mut completion = if let Some(data) = progress_tree.get(uuid.as_bytes())? {
+                decode(&data)?
             } else {
                 CourseCompletion::default()
             };
 
+            // Updated completion is stored the next time the Course's completion is updated
+            completion.resolve_textbook_ids(&course);
+
+            let fingerprint: Option<String> = match fingerprint_tree.get(uuid.as_bytes())? {
+                Some(data) => Some(decode(&data)?),
+                None => None,
+            };
+
+            if fingerprint.as_ref() != Some(&course.fingerprint()) {
+                completion.apply_migrations(&course);
+            }
+
             let progress = CourseProgress::calculate(&course, &completion);
 
             Ok((course, completion, progress))
         })
         .await?
     }
+    /// Stores a Course's completion, along with the fingerprint of the Course it was calculated for.
     pub async fn set_course_completion(
         &self,
         course: Course,
         data: CourseCompletion,
     ) -> Result<(), Error> {
         let progress_tree = self.root.open_tree(PROGRESS_TREE_KEY)?;
+        let fingerprint_tree = self.root.open_tree(FINGERPRINT_TREE_KEY)?;
 
         task::spawn_blocking(move || {
-            progress_tree.transaction(|progress_tree| {
-                let uuid = course.uuid.unwrap();
+            let fingerprint = course.fingerprint();
+            let encoded_fingerprint = encode(&fingerprint)?;
 
-                let old_completion = if let Some(data) = progress_tree.get(uuid.as_bytes())? {
-                    bincode::deserialize(&data).map_err(ConflictableTransactionError::Abort)?
-                } else {
-                    CourseCompletion::default()
-                };
+            (&progress_tree, &fingerprint_tree).transaction(
+                |(progress_tree, fingerprint_tree)| {
+                    let uuid = course.uuid.unwrap();
 
-                let old_progress = CourseProgress::calculate(&course, &old_completion);
-                let new_progress = CourseProgress::calculate(&course, &data);
+                    let mut old_completion: CourseCompletion =
+                        if let Some(data) = progress_tree.get(uuid.as_bytes())? {
+                            decode(&data).map_err(ConflictableTransactionError::Abort)?
+                        } else {
+                            CourseCompletion::default()
+                        };
+                    old_completion.resolve_textbook_ids(&course);
 
-                progress_tree.insert(
-                    uuid.as_bytes(),
-                    bincode::serialize(&data).map_err(ConflictableTransactionError::Abort)?,
-                )?;
+                    let old_fingerprint: Option<String> =
+                        match fingerprint_tree.get(uuid.as_bytes())? {
+                            Some(data) => {
+                                Some(decode(&data).map_err(ConflictableTransactionError::Abort)?)
+                            }
+                            None => None,
+                        };
 
-                let time_change_secs =
-                    CourseCompletion::calculate_time_diff_secs(&old_completion, &data);
-                let chapter_change =
-                    CourseProgress::calculate_chapter_diff(&old_progress, &new_progress);
+                    // Migrated sections shouldn't be counted as new progress
+                    if old_fingerprint.as_ref() != Some(&fingerprint) {
+                        old_completion.apply_migrations(&course);
+                    }
 
-                let mut overall = if let Some(data) = progress_tree.get(OVERALL_PROGRESS_KEY)? {
-                    bincode::deserialize(&data).map_err(ConflictableTransactionError::Abort)?
-                } else {
-                    OverallProgress::default()
-                };
-                overall.update(chapter_change, time_change_secs);
+                    let old_progress = CourseProgress::calculate(&course, &old_completion);
+                    let new_progress = CourseProgress::calculate(&course, &data);
 
-                progress_tree.insert(
-                    OVERALL_PROGRESS_KEY,
-                    bincode::serialize(&overall).map_err(ConflictableTransactionError::Abort)?,
-                )?;
+                    progress_tree.insert(
+                        uuid.as_bytes(),
+                        encode(&data).map_err(ConflictableTransactionError::Abort)?,
+                    )?;
+                    fingerprint_tree.insert(uuid.as_bytes(), encoded_fingerprint.clone())?;
+
+                    let time_change_secs =
+                        CourseCompletion::calculate_time_diff_secs(&old_completion, &data);
+                    let chapter_change =
+                        CourseProgress::calculate_chapter_diff(&old_progress, &new_progress);
+
+                    let mut overall = if let Some(data) = progress_tree.get(OVERALL_PROGRESS_KEY)? {
+                        decode(&data).map_err(ConflictableTransactionError::Abort)?
+                    } else {
+                        OverallProgress::default()
+                    };
+                    overall.update(chapter_change, time_change_secs);
 
-                Ok(())
-            })?;
+                    progress_tree.insert(
+                        OVERALL_PROGRESS_KEY,
+                        encode(&overall).map_err(ConflictableTransactionError::Abort)?,
+                    )?;
+
+                    Ok(())
+                },
+            )?;
 
             Ok(())
         })
@@ -123,7 +247,7 @@ impl Database {
 
         task::spawn_blocking(move || {
             if let Some(data) = progress_tree.get(OVERALL_PROGRESS_KEY)? {
-                Ok(bincode::deserialize(&data)?)
+                Ok(decode(&data)?)
             } else {
                 Ok(OverallProgress::default())
             }
@@ -135,7 +259,7 @@ impl Database {
 
         task::spawn_blocking(move || {
             if let Some(data) = root_tree.get(ACTIVE_COURSES_KEY)? {
-                Ok(bincode::deserialize(&data)?)
+                Ok(decode(&data)?)
             } else {
                 Ok(Vec::new())
             }
@@ -146,7 +270,7 @@ impl Database {
         let root_tree = self.root.deref().clone();
 
         task::spawn_blocking(move || {
-            root_tree.insert(ACTIVE_COURSES_KEY, bincode::serialize(&data)?)?;
+            root_tree.insert(ACTIVE_COURSES_KEY, encode(&data)?)?;
 
             Ok(())
         })
@@ -157,7 +281,7 @@ impl Database {
 
         task::spawn_blocking(move || {
             if let Some(data) = root_tree.get(SETTINGS_KEY)? {
-                Ok(bincode::deserialize(&data)?)
+                Ok(decode(&data)?)
             } else {
                 Ok(String::new())
             }
@@ -168,10 +292,355 @@ impl Database {
         let root_tree = self.root.deref().clone();
 
         task::spawn_blocking(move || {
-            root_tree.insert(SETTINGS_KEY, bincode::serialize(&data)?)?;
+            root_tree.insert(SETTINGS_KEY, encode(&data)?)?;
 
             Ok(())
         })
         .await?
     }
+    /// Returns the annotations within a Course, in the order they were created.
+    ///
+    /// If the Course has changed since its annotations were last read, they are moved to their textbook's id and migrated first, and the changed annotations are stored.
+    pub async fn get_annotations(&self, course: Course) -> Result<Vec<Annotation>, Error> {
+        let annotation_tree = self.root.open_tree(ANNOTATION_TREE_KEY)?;
+        let fingerprint_tree = self.root.open_tree(ANNOTATION_FINGERPRINT_TREE_KEY)?;
+
+        task::spawn_blocking(move || {
+            let uuid = course.uuid.unwrap();
+            let fingerprint = course.fingerprint();
+
+            let stored_fingerprint: Option<String> = match fingerprint_tree.get(uuid.as_bytes())? {
+                Some(data) => Some(decode(&data)?),
+                None => None,
+            };
+
+            if stored_fingerprint.as_ref() != Some(&fingerprint) {
+                let keys = annotation_tree
+                    .scan_prefix(uuid.as_bytes())
+                    .keys()
+                    .collect::<Result<Vec<_>, _>>()?;
+                let encoded_fingerprint = encode(&fingerprint)?;
+
+                (&annotation_tree, &fingerprint_tree).transaction(
+                    |(annotation_tree, fingerprint_tree)| {
+                        for key in &keys {
+                            let Some(data) = annotation_tree.get(key)? else {
+                                continue;
+                            };
+                            let mut annotation: Annotation =
+                                decode(&data).map_err(ConflictableTransactionError::Abort)?;
+
+                            let resolved = annotation.resolve_textbook_id(&course);
+                            let migrated = annotation.apply_migrations(&course);
+
+                            if resolved || migrated {
+                                annotation_tree.insert(
+                                    key,
+                                    encode(&annotation)
+                                        .map_err(ConflictableTransactionError::Abort)?,
+                                )?;
+                            }
+                        }
+
+                        fingerprint_tree.insert(uuid.as_bytes(), encoded_fingerprint.clone())?;
+
+                        Ok::<_, ConflictableTransactionError<Error>>(())
+                    },
+                )?;
+            }
+
+            let mut annotations = Vec::new();
+
+            for entry in annotation_tree.scan_prefix(uuid.as_bytes()) {
+                let (_, value) = entry?;
+                let annotation: Annotation = decode(&value)?;
+
+                annotations.push(annotation);
+            }
+
+            annotations.sort_by_key(|annotation| annotation.created);
+
+            Ok(annotations)
+        })
+        .await?
+    }
+    /// Stores a new annotation within a Course, assigning its UUID and creation time.
+    pub async fn create_annotation(
+        &self,
+        course: Uuid,
+        mut annotation: Annotation,
+    ) -> Result<Annotation, Error> {
+        let annotation_tree = self.root.open_tree(ANNOTATION_TREE_KEY)?;
+
+        task::spawn_blocking(move || {
+            annotation.uuid = Uuid::new_v4();
+            annotation.created = Utc::now();
+            annotation.modified = annotation.created;
+
+            annotation_tree.insert(
+                annotation_key(course, annotation.uuid),
+                encode(&annotation)?,
+            )?;
+
+            Ok(annotation)
+        })
+        .await?
+    }
+    /// Replaces an existing annotation within a Course, keeping its creation time.
+    pub async fn update_annotation(
+        &self,
+        course: Uuid,
+        annotation: Annotation,
+    ) -> Result<Annotation, Error> {
+        let annotation_tree = self.root.open_tree(ANNOTATION_TREE_KEY)?;
+
+        task::spawn_blocking(move || {
+            let key = annotation_key(course, annotation.uuid);
+
+            Ok(annotation_tree.transaction(|annotation_tree| {
+                let Some(data) = annotation_tree.get(key)? else {
+                    return Err(ConflictableTransactionError::Abort(
+                        Error::UnknownAnnotation(annotation.uuid),
+                    ));
+                };
+                let existing: Annotation =
+                    decode(&data).map_err(ConflictableTransactionError::Abort)?;
+
+                let mut updated = annotation.clone();
+                updated.created = existing.created;
+                updated.modified = Utc::now();
+
+                annotation_tree.insert(
+                    &key,
+                    encode(&updated).map_err(ConflictableTransactionError::Abort)?,
+                )?;
+
+                Ok(updated)
+            })?)
+        })
+        .await?
+    }
+    /// Removes an annotation from a Course.
+    pub async fn delete_annotation(&self, course: Uuid, annotation: Uuid) -> Result<(), Error> {
+        let annotation_tree = self.root.open_tree(ANNOTATION_TREE_KEY)?;
+
+        task::spawn_blocking(move || {
+            match annotation_tree.remove(annotation_key(course, annotation))? {
+                Some(_) => Ok(()),
+                None => Err(Error::UnknownAnnotation(annotation)),
+            }
+        })
+        .await?
+    }
+    /// Creates a ``ProgressBackup`` containing all progress, active Courses, and settings.
+    pub async fn export_progress(&self) -> Result<ProgressBackup, Error> {
+        let root_tree = self.root.deref().clone();
+        let progress_tree = self.root.open_tree(PROGRESS_TREE_KEY)?;
+        let fingerprint_tree = self.root.open_tree(FINGERPRINT_TREE_KEY)?;
+        let annotation_tree = self.root.open_tree(ANNOTATION_TREE_KEY)?;
+
+        task::spawn_blocking(move || {
+            read_backup(
+                &root_tree,
+                &progress_tree,
+                &fingerprint_tree,
+                &annotation_tree,
+            )
+        })
+        .await?
+    }
+    /// Imports a ``ProgressBackup``, either merging it with or replacing the existing data.
+    ///
+    /// All changes are applied in a single transaction, so a failed import leaves the existing data untouched.
+    pub async fn import_progress(
+        &self,
+        backup: ProgressBackup,
+        mode: ImportMode,
+    ) -> Result<(), Error> {
+        let root_tree = self.root.deref().clone();
+        let progress_tree = self.root.open_tree(PROGRESS_TREE_KEY)?;
+        let fingerprint_tree = self.root.open_tree(FINGERPRINT_TREE_KEY)?;
+        let annotation_tree = self.root.open_tree(ANNOTATION_TREE_KEY)?;
+        let annotation_fingerprint_tree = self.root.open_tree(ANNOTATION_FINGERPRINT_TREE_KEY)?;
+
+        task::spawn_blocking(move || {
+            let existing = read_backup(
+                &root_tree,
+                &progress_tree,
+                &fingerprint_tree,
+                &annotation_tree,
+            )?;
+            let existing_courses: Vec<Uuid> = existing.courses.keys().copied().collect();
+            let existing_fingerprints: Vec<Uuid> = existing.fingerprints.keys().copied().collect();
+            let existing_annotations: Vec<[u8; 32]> = existing
+                .annotations
+                .iter()
+                .flat_map(|(course, annotations)| {
+                    annotations.iter().map(|a| annotation_key(*course, a.uuid))
+                })
+                .collect();
+
+            let backup = match mode {
+                ImportMode::Merge => {
+                    let mut merged = existing;
+                    merged.merge(backup);
+                    merged
+                }
+                ImportMode::Replace => backup,
+            };
+
+            let removed_fingerprints: Vec<Uuid> = existing_fingerprints
+                .into_iter()
+                .filter(|uuid| !backup.fingerprints.contains_key(uuid))
+                .collect();
+            let removed_courses: Vec<Uuid> = existing_courses
+                .into_iter()
+                .filter(|uuid| !backup.courses.contains_key(uuid))
+                .collect();
+
+            let mut courses = Vec::with_capacity(backup.courses.len());
+            for (uuid, completion) in &backup.courses {
+                courses.push((*uuid, encode(completion)?));
+            }
+
+            let mut fingerprints = Vec::with_capacity(backup.fingerprints.len());
+            for (uuid, fingerprint) in &backup.fingerprints {
+                fingerprints.push((*uuid, encode(fingerprint)?));
+            }
+
+            let mut annotations = HashMap::new();
+            for (course, course_annotations) in &backup.annotations {
+                for annotation in course_annotations {
+                    annotations.insert(
+                        annotation_key(*course, annotation.uuid),
+                        encode(annotation)?,
+                    );
+                }
+            }
+
+            let removed_annotations: Vec<[u8; 32]> = existing_annotations
+                .into_iter()
+                .filter(|key| !annotations.contains_key(key))
+                .collect();
+
+            let overall = encode(&backup.overall)?;
+            let active_courses = encode(&backup.active_courses)?;
+            let settings = encode(&backup.settings)?;
+
+            // Imported annotations are migrated when they're next read. Clearing this before the import is harmless if the import fails, as it only causes annotations to be checked again
+            annotation_fingerprint_tree.clear()?;
+
+            (
+                &root_tree,
+                &progress_tree,
+                &fingerprint_tree,
+                &annotation_tree,
+            )
+                .transaction(
+                    |(root_tree, progress_tree, fingerprint_tree, annotation_tree)| {
+                        for key in &removed_annotations {
+                            annotation_tree.remove(key)?;
+                        }
+
+                        for (key, data) in &annotations {
+                            annotation_tree.insert(key, data.clone())?;
+                        }
+
+                        for uuid in &removed_courses {
+                            progress_tree.remove(uuid.as_bytes())?;
+                        }
+
+                        for uuid in &removed_fingerprints {
+                            fingerprint_tree.remove(uuid.as_bytes())?;
+                        }
+
+                        for (uuid, data) in &courses {
+                            progress_tree.insert(uuid.as_bytes(), data.clone())?;
+                        }
+
+                        for (uuid, data) in &fingerprints {
+                            fingerprint_tree.insert(uuid.as_bytes(), data.clone())?;
+                        }
+
+                        progress_tree.insert(OVERALL_PROGRESS_KEY, overall.clone())?;
+                        root_tree.insert(ACTIVE_COURSES_KEY, active_courses.clone())?;
+                        root_tree.insert(SETTINGS_KEY, settings.clone())?;
+
+                        Ok::<_, ConflictableTransactionError<Error>>(())
+                    },
+                )?;
+
+            Ok(())
+        })
+        .await?
+    }
+}
+
+fn annotation_key(course: Uuid, annotation: Uuid) -> [u8; 32] {
+    let mut key = [0; 32];
+
+    key[..16].copy_from_slice(course.as_bytes());
+    key[16..].copy_from_slice(annotation.as_bytes());
+
+    key
+}
+
+/// Reads all stored data into a ``ProgressBackup``.
+fn read_backup(
+    root_tree: &Tree,
+    progress_tree: &Tree,
+    fingerprint_tree: &Tree,
+    annotation_tree: &Tree,
+) -> Result<ProgressBackup, Error> {
+    let mut courses = HashMap::new();
+    let mut overall = OverallProgress::default();
+
+    for entry in progress_tree {
+        let (key, value) = entry?;
+
+        if let Ok(uuid) = Uuid::from_slice(&key) {
+            courses.insert(uuid, decode(&value)?);
+        } else if *key == *OVERALL_PROGRESS_KEY {
+            overall = decode(&value)?;
+        }
+    }
+
+    let mut fingerprints = HashMap::new();
+
+    for entry in fingerprint_tree {
+        let (key, value) = entry?;
+
+        if let Ok(uuid) = Uuid::from_slice(&key) {
+            fingerprints.insert(uuid, decode(&value)?);
+        }
+    }
+
+    let mut annotations: HashMap<Uuid, Vec<Annotation>> = HashMap::new();
+
+    for entry in annotation_tree {
+        let (key, value) = entry?;
+
+        if let Some(course) = key.get(..16).and_then(|key| Uuid::from_slice(key).ok()) {
+            annotations.entry(course).or_default().push(decode(&value)?);
+        }
+    }
+
+    let active_courses = match root_tree.get(ACTIVE_COURSES_KEY)? {
+        Some(data) => decode(&data)?,
+        None => Vec::new(),
+    };
+
+    let settings = match root_tree.get(SETTINGS_KEY)? {
+        Some(data) => decode(&data)?,
+        None => String::new(),
+    };
+
+    Ok(ProgressBackup::new(
+        courses,
+        fingerprints,
+        annotations,
+        overall,
+        active_courses,
+        settings,
+    ))
 }
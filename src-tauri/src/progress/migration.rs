@@ -0,0 +1,454 @@
+use serde::{de::DeserializeOwned, Serialize};
+use sled::{
+    transaction::{ConflictableTransactionError, Transactional},
+    Batch, Db, Tree,
+};
+use uuid::Uuid;
+
+use super::database::{
+    Envelope, Error, ACTIVE_COURSES_KEY, OVERALL_PROGRESS_KEY, PROGRESS_TREE_KEY, SETTINGS_KEY,
+};
+
+/// The version of the database layout written by this version of the application
+///
+/// Databases created before versioning was introduced (up to v0.4.x) have schema version 0.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Stored in the root tree as a big-endian integer, so that it can be read regardless of how records are encoded.
+pub(super) const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+/// Changes made by a ``Migration``, which are applied in a single transaction along with the new schema version
+#[derive(Default)]
+struct Changes {
+    root: Batch,
+    progress: Batch,
+}
+
+/// Upgrades a database from one schema version to the next, without modifying it
+///
+/// Migrations must only use the record types defined in this module, as the application's own types will change in later versions.
+type Migration = fn(root: &Tree, progress: &Tree) -> Result<Changes, Error>;
+
+/// The migration at index `n` upgrades a database from schema version `n` to `n + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [wrap_records, key_textbooks_by_id];
+
+/// Upgrades a database to the current schema version, applying each migration in order.
+///
+/// Databases created by a newer version of the application are rejected, as their records can't be decoded.
+pub(super) fn migrate(db: &Db) -> Result<(), Error> {
+    let root: &Tree = db;
+    let progress = db.open_tree(PROGRESS_TREE_KEY)?;
+
+    let mut version = schema_version(root)?;
+
+    if version > SCHEMA_VERSION {
+        return Err(Error::UnsupportedSchemaVersion(version));
+    }
+
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    while version < SCHEMA_VERSION {
+        let changes = MIGRATIONS[version as usize](root, &progress)?;
+        version += 1;
+
+        (root, &progress).transaction(|(root, progress)| {
+            root.apply_batch(&changes.root)?;
+            progress.apply_batch(&changes.progress)?;
+            root.insert(SCHEMA_VERSION_KEY, &version.to_be_bytes())?;
+
+            Ok::<_, ConflictableTransactionError<Error>>(())
+        })?;
+    }
+
+    db.flush()?;
+
+    Ok(())
+}
+
+/// Returns the schema version of a database, which is 0 if it has never been migrated.
+pub(super) fn schema_version(root: &Tree) -> Result<u32, Error> {
+    match root.get(SCHEMA_VERSION_KEY)? {
+        Some(data) => match <[u8; 4]>::try_from(&*data) {
+            Ok(bytes) => Ok(u32::from_be_bytes(bytes)),
+            Err(_) => Err(Error::UnsupportedSchemaVersion(u32::MAX)),
+        },
+        None => Ok(0),
+    }
+}
+
+/// Records as they were stored in schema versions 0 and 1
+mod v1 {
+    use std::collections::{HashMap, HashSet};
+
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    pub struct CourseCompletion {
+        pub time_spent: HashMap<NaiveDate, u64>,
+        pub books: HashMap<usize, CourseCompletionTextbook>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct CourseCompletionTextbook {
+        pub completed_sections: HashSet<String>,
+        pub position: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct OverallProgress {
+        pub chapters_completed: HashMap<NaiveDate, f32>,
+        pub time_spent: HashMap<NaiveDate, i64>,
+    }
+}
+
+/// Records as they were stored in schema version 2
+mod v2 {
+    use std::collections::HashMap;
+
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Serialize};
+
+    pub use super::v1::CourseCompletionTextbook;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct CourseCompletion {
+        pub time_spent: HashMap<NaiveDate, u64>,
+        pub books: HashMap<String, CourseCompletionTextbook>,
+    }
+}
+
+/// Schema version 0 to 1: Wraps every record in a versioned envelope.
+fn wrap_records(root: &Tree, progress: &Tree) -> Result<Changes, Error> {
+    let mut changes = Changes::default();
+
+    wrap_record::<String>(root, &mut changes.root, SETTINGS_KEY)?;
+    wrap_record::<Vec<Uuid>>(root, &mut changes.root, ACTIVE_COURSES_KEY)?;
+    wrap_record::<v1::OverallProgress>(progress, &mut changes.progress, OVERALL_PROGRESS_KEY)?;
+
+    for key in progress.iter().keys() {
+        let key = key?;
+
+        if key.len() == 16 {
+            wrap_record::<v1::CourseCompletion>(progress, &mut changes.progress, &key)?;
+        }
+    }
+
+    Ok(changes)
+}
+
+fn wrap_record<T: Serialize + DeserializeOwned>(
+    tree: &Tree,
+    batch: &mut Batch,
+    key: &[u8],
+) -> Result<(), Error> {
+    if let Some(data) = tree.get(key)? {
+        let value: T = bincode::deserialize(&data)?;
+
+        batch.insert(key, bincode::serialize(&Envelope { version: 1, value })?);
+    }
+
+    Ok(())
+}
+
+/// Schema version 1 to 2: Keys textbook completion by ``Textbook::progress_key`` instead of by position.
+///
+/// Existing completion is keyed by the textbook's position, which is the key used for textbooks without an id. Completion is moved to a textbook's id when the Course is next loaded.
+fn key_textbooks_by_id(_root: &Tree, progress: &Tree) -> Result<Changes, Error> {
+    let mut changes = Changes::default();
+
+    for entry in progress {
+        let (key, data) = entry?;
+
+        if key.len() != 16 {
+            continue;
+        }
+
+        let envelope: Envelope<v1::CourseCompletion> = bincode::deserialize(&data)?;
+
+        if envelope.version != 1 {
+            return Err(Error::UnsupportedRecordVersion(envelope.version));
+        }
+
+        let completion = v2::CourseCompletion {
+            time_spent: envelope.value.time_spent,
+            books: envelope
+                .value
+                .books
+                .into_iter()
+                .map(|(index, book)| (index.to_string(), book))
+                .collect(),
+        };
+
+        changes.progress.insert(
+            key,
+            bincode::serialize(&Envelope {
+                version: 2,
+                value: completion,
+            })?,
+        );
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        env,
+        path::PathBuf,
+    };
+
+    use chrono::NaiveDate;
+    use sled::{Config, Db, Tree};
+    use uuid::Uuid;
+
+    use super::{
+        super::{
+            super::course::{Course, Textbook},
+            database::{
+                decode, Envelope, Error, ACTIVE_COURSES_KEY, OVERALL_PROGRESS_KEY,
+                PROGRESS_TREE_KEY, SETTINGS_KEY,
+            },
+            CourseCompletion, OverallProgress,
+        },
+        migrate, schema_version, v1, SCHEMA_VERSION, SCHEMA_VERSION_KEY,
+    };
+
+    const SETTINGS: &str = "{\"show_course_clock\":true}";
+
+    /// Opens an empty database, which is deleted once it is dropped.
+    fn temporary_database() -> Db {
+        // Temporary databases use a time-based path by default, which can collide when tests are run in parallel
+        Config::new()
+            .temporary(true)
+            .path(env::temp_dir().join(format!("portal-test-{}", Uuid::new_v4())))
+            .open()
+            .unwrap()
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, 14).unwrap()
+    }
+
+    fn sections() -> HashSet<String> {
+        HashSet::from(["intro".to_string(), "ch1".to_string()])
+    }
+
+    fn completion() -> v1::CourseCompletion {
+        v1::CourseCompletion {
+            time_spent: HashMap::from([(date(), 1800)]),
+            books: HashMap::from([(
+                1,
+                v1::CourseCompletionTextbook {
+                    completed_sections: sections(),
+                    position: Some("epubcfi(/6/4)".to_string()),
+                },
+            )]),
+        }
+    }
+
+    fn overall() -> v1::OverallProgress {
+        v1::OverallProgress {
+            chapters_completed: HashMap::from([(date(), 1.5)]),
+            time_spent: HashMap::from([(date(), 1800)]),
+        }
+    }
+
+    /// Creates a database using the layout of v0.4.x, where every record is stored as raw bincode.
+    fn legacy_database(course: Uuid) -> (Db, Tree) {
+        let db = temporary_database();
+        let progress = db.open_tree(PROGRESS_TREE_KEY).unwrap();
+
+        db.insert(SETTINGS_KEY, bincode::serialize(SETTINGS).unwrap())
+            .unwrap();
+        db.insert(
+            ACTIVE_COURSES_KEY,
+            bincode::serialize(&vec![course]).unwrap(),
+        )
+        .unwrap();
+        progress
+            .insert(
+                course.as_bytes(),
+                bincode::serialize(&completion()).unwrap(),
+            )
+            .unwrap();
+        progress
+            .insert(
+                OVERALL_PROGRESS_KEY,
+                bincode::serialize(&overall()).unwrap(),
+            )
+            .unwrap();
+
+        (db, progress)
+    }
+
+    /// Creates a database using schema version 1, where every record is wrapped in an envelope.
+    fn version_1_database(course: Uuid) -> (Db, Tree) {
+        fn wrap<T: serde::Serialize>(value: T) -> Vec<u8> {
+            bincode::serialize(&Envelope { version: 1, value }).unwrap()
+        }
+
+        let db = temporary_database();
+        let progress = db.open_tree(PROGRESS_TREE_KEY).unwrap();
+
+        db.insert(SCHEMA_VERSION_KEY, &1_u32.to_be_bytes()).unwrap();
+        db.insert(SETTINGS_KEY, wrap(SETTINGS)).unwrap();
+        db.insert(ACTIVE_COURSES_KEY, wrap(vec![course])).unwrap();
+        progress
+            .insert(course.as_bytes(), wrap(completion()))
+            .unwrap();
+        progress
+            .insert(OVERALL_PROGRESS_KEY, wrap(overall()))
+            .unwrap();
+
+        (db, progress)
+    }
+
+    fn assert_upgraded(db: &Db, progress: &Tree, course: Uuid) {
+        assert_eq!(schema_version(db).unwrap(), SCHEMA_VERSION);
+
+        let settings: String = decode(&db.get(SETTINGS_KEY).unwrap().unwrap()).unwrap();
+        assert_eq!(settings, SETTINGS);
+
+        let active_courses: Vec<Uuid> =
+            decode(&db.get(ACTIVE_COURSES_KEY).unwrap().unwrap()).unwrap();
+        assert_eq!(active_courses, vec![course]);
+
+        let stored: CourseCompletion =
+            decode(&progress.get(course.as_bytes()).unwrap().unwrap()).unwrap();
+        assert_eq!(stored.time_spent, completion().time_spent);
+        assert_eq!(stored.books.len(), 1);
+        assert_eq!(stored.books["1"].completed_sections, sections());
+        assert_eq!(stored.books["1"].position.as_deref(), Some("epubcfi(/6/4)"));
+
+        let stored: OverallProgress =
+            decode(&progress.get(OVERALL_PROGRESS_KEY).unwrap().unwrap()).unwrap();
+        assert_eq!(stored.chapters_completed, overall().chapters_completed);
+        assert_eq!(stored.time_spent, overall().time_spent);
+    }
+
+    #[test]
+    fn upgrades_legacy_layout() {
+        let course = Uuid::new_v4();
+        let (db, progress) = legacy_database(course);
+
+        assert_eq!(schema_version(&db).unwrap(), 0);
+
+        migrate(&db).unwrap();
+
+        assert_upgraded(&db, &progress, course);
+    }
+
+    #[test]
+    fn upgrades_version_1() {
+        let course = Uuid::new_v4();
+        let (db, progress) = version_1_database(course);
+
+        migrate(&db).unwrap();
+
+        assert_upgraded(&db, &progress, course);
+    }
+
+    #[test]
+    fn upgrades_empty_database() {
+        let db = temporary_database();
+
+        migrate(&db).unwrap();
+
+        assert_eq!(schema_version(&db).unwrap(), SCHEMA_VERSION);
+        assert!(db.get(SETTINGS_KEY).unwrap().is_none());
+        assert!(db.get(ACTIVE_COURSES_KEY).unwrap().is_none());
+    }
+
+    #[test]
+    fn migrates_only_once() {
+        let course = Uuid::new_v4();
+        let (db, progress) = legacy_database(course);
+
+        migrate(&db).unwrap();
+        migrate(&db).unwrap();
+
+        assert_upgraded(&db, &progress, course);
+    }
+
+    #[test]
+    fn moves_completion_to_textbook_id() {
+        let course = Uuid::new_v4();
+        let (db, progress) = legacy_database(course);
+
+        migrate(&db).unwrap();
+
+        let textbook = |id: Option<&str>| Textbook {
+            id: id.map(str::to_string),
+            label: String::new(),
+            file: PathBuf::new(),
+            chapters: Vec::new(),
+        };
+
+        let course = Course {
+            uuid: Some(course),
+            title: String::new(),
+            description: None,
+            books: vec![textbook(None), textbook(Some("second"))],
+            migrations: BTreeMap::new(),
+        };
+
+        let mut stored: CourseCompletion = decode(
+            &progress
+                .get(course.uuid.unwrap().as_bytes())
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert!(stored.resolve_textbook_ids(&course));
+        assert!(!stored.books.contains_key("1"));
+        assert_eq!(stored.books["second"].completed_sections, sections());
+
+        assert!(!stored.resolve_textbook_ids(&course));
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let db = temporary_database();
+        db.insert(SCHEMA_VERSION_KEY, &(SCHEMA_VERSION + 1).to_be_bytes())
+            .unwrap();
+
+        assert!(matches!(
+            migrate(&db),
+            Err(Error::UnsupportedSchemaVersion(version)) if version == SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_newer_records() {
+        let data = bincode::serialize(&(99_u32, SETTINGS.to_string())).unwrap();
+
+        assert!(matches!(
+            decode::<String>(&data),
+            Err(Error::UnsupportedRecordVersion(99))
+        ));
+    }
+
+    #[test]
+    fn failed_migration_leaves_database_unchanged() {
+        let course = Uuid::new_v4();
+        let (db, progress) = legacy_database(course);
+
+        let corrupted = Uuid::new_v4();
+        progress.insert(corrupted.as_bytes(), &[0xFF]).unwrap();
+
+        let settings = db.get(SETTINGS_KEY).unwrap();
+        let completion = progress.get(course.as_bytes()).unwrap();
+
+        assert!(matches!(migrate(&db), Err(Error::Encoding(_))));
+
+        assert_eq!(schema_version(&db).unwrap(), 0);
+        assert_eq!(db.get(SETTINGS_KEY).unwrap(), settings);
+        assert_eq!(progress.get(course.as_bytes()).unwrap(), completion);
+    }
+}
@@ -7,7 +7,12 @@ use serde::{Deserialize, Serialize};
 
 use super::course::Course;
 
+pub mod annotations;
+pub mod backup;
 pub mod database;
+mod migration;
+pub mod profiles;
+pub mod recommendation;
 
 /// The raw data used to keep track of ``Course`` completion
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -15,8 +20,8 @@ pub mod database;
 pub struct CourseCompletion {
     /// The amount of time spent in the ``Course``, by day.
     pub time_spent: HashMap<NaiveDate, u64>,
-    /// The raw data used to keep track of ``Textbook`` completion.
-    pub books: HashMap<usize, CourseCompletionTextbook>,
+    /// The raw data used to keep track of ``Textbook`` completion, keyed by ``Textbook::progress_key``.
+    pub books: HashMap<String, CourseCompletionTextbook>,
 }
 
 /// The raw data used to keep track of ``Textbook`` completion
@@ -29,6 +34,64 @@ pub struct CourseCompletionTextbook {
 }
 
 impl CourseCompletion {
+    /// Moves textbook completion stored using a textbook's position to its id, for textbooks which have been given an id since the completion was stored.
+    ///
+    /// Returns true if any completion was moved.
+    pub fn resolve_textbook_ids(&mut self, course: &Course) -> bool {
+        let mut changed = false;
+
+        for (book_index, book) in course.books.iter().enumerate() {
+            if let Some(id) = &book.id {
+                if self.books.contains_key(id) {
+                    continue;
+                }
+
+                if let Some(completion) = self.books.remove(&book_index.to_string()) {
+                    self.books.insert(id.clone(), completion);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+    /// Replaces completed sections using the migrations of each textbook.
+    ///
+    /// Migrations whose old href is still used by their textbook are ignored, so applying the same migrations again has no further effect. Returns true if any completion was changed.
+    pub fn apply_migrations(&mut self, course: &Course) -> bool {
+        let mut changed = false;
+
+        for (book_index, book) in course.books.iter().enumerate() {
+            let key = book.progress_key(book_index);
+
+            let (Some(migrations), Some(completion)) =
+                (course.migrations.get(&key), self.books.get_mut(&key))
+            else {
+                continue;
+            };
+
+            let hrefs = book.hrefs();
+
+            let mut completed_sections =
+                HashSet::with_capacity(completion.completed_sections.len());
+
+            for section in completion.completed_sections.drain() {
+                match migrations.get(&section) {
+                    Some(new) if !hrefs.contains(section.as_str()) => {
+                        completed_sections.insert(new.clone());
+                        changed = true;
+                    }
+                    _ => {
+                        completed_sections.insert(section);
+                    }
+                }
+            }
+
+            completion.completed_sections = completed_sections;
+        }
+
+        changed
+    }
     fn calculate_time_diff_secs(before: &Self, after: &Self) -> i64 {
         let mut before_total = 0;
         for before_date in before.time_spent.values() {
@@ -66,12 +129,28 @@ pub struct TextbookProgress {
 }
 
 impl CourseProgress {
+    /// Returns the average completion (ranging between 0 and 1) of all completable textbooks in the course, or ``None`` if the course can't be completed.
+    pub fn overall_completion(&self) -> Option<f32> {
+        let mut total = 0.0;
+        let mut count: u16 = 0;
+
+        for book in self.completion.iter().flatten() {
+            total += book.overall_completion;
+            count = count.saturating_add(1);
+        }
+
+        if count == 0 {
+            None
+        } else {
+            Some(total / f32::from(count))
+        }
+    }
     fn calculate(course: &Course, completion: &CourseCompletion) -> Self {
         let mut book_progress = Vec::with_capacity(course.books.len());
 
         for (book_index, book) in course.books.iter().enumerate() {
             #[allow(clippy::cast_precision_loss)]
-            book_progress.push(match completion.books.get(&book_index) {
+            book_progress.push(match completion.books.get(&book.progress_key(book_index)) {
                 Some(book_completion) => {
                     let mut chapter_progress = Vec::with_capacity(book.chapters.len());
 
@@ -222,3 +301,78 @@ impl OverallProgress {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{CourseCompletion, CourseCompletionTextbook};
+    use crate::course::Course;
+
+    fn completion(sections: &[&str]) -> CourseCompletionTextbook {
+        CourseCompletionTextbook {
+            completed_sections: sections.iter().map(ToString::to_string).collect(),
+            position: None,
+        }
+    }
+
+    fn sections<'a>(completion: &'a CourseCompletion, key: &str) -> HashSet<&'a str> {
+        completion.books[key]
+            .completed_sections
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn migrations_are_scoped_to_their_textbook() {
+        let course: Course = toml::from_str(
+            r#"
+title = "Course"
+
+[[books]]
+id = "algebra"
+label = "Algebra"
+file = "algebra"
+chapters = [{ groups = [{ sections = ["intro.xhtml", "limits.xhtml"] }] }]
+
+[[books]]
+id = "geometry"
+label = "Geometry"
+file = "geometry"
+chapters = [{ groups = [{ sections = ["start.xhtml"] }] }]
+
+[migrations.algebra]
+"limits-old.xhtml" = "limits.xhtml"
+
+[migrations.geometry]
+"intro.xhtml" = "start.xhtml"
+"#,
+        )
+        .unwrap();
+
+        let mut course_completion = CourseCompletion::default();
+        course_completion.books.insert(
+            "algebra".to_string(),
+            completion(&["intro.xhtml", "limits-old.xhtml"]),
+        );
+        course_completion.books.insert(
+            "geometry".to_string(),
+            completion(&["intro.xhtml", "limits-old.xhtml"]),
+        );
+
+        assert!(course_completion.apply_migrations(&course));
+
+        // Algebra still uses intro.xhtml, which doesn't stop Geometry's migration of it
+        assert_eq!(
+            sections(&course_completion, "algebra"),
+            HashSet::from(["intro.xhtml", "limits.xhtml"])
+        );
+        assert_eq!(
+            sections(&course_completion, "geometry"),
+            HashSet::from(["start.xhtml", "limits-old.xhtml"])
+        );
+
+        assert!(!course_completion.apply_migrations(&course));
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::task::{self, JoinError};
+use uuid::{fmt::Simple, Uuid};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error("Task was terminated or panicked")]
+    BlockingTaskFailed(#[from] JoinError),
+    #[error("Profile {0} does not exist")]
+    UnknownProfile(Uuid),
+    #[error("Profile names must not be empty")]
+    EmptyName,
+    #[error("The active profile can't be deleted")]
+    ActiveProfile,
+}
+
+/// A learner using the application, who has their own progress, settings, and active Courses
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+/// The list of profiles, along with the profile which is currently in use
+///
+/// Every profile shares the same User Resources folder, but has a separate database. The default profile (which uses the nil UUID) is created automatically, and uses the database created before profiles were supported.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileRegistry {
+    pub active: Uuid,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        Self {
+            active: Uuid::nil(),
+            profiles: vec![Profile {
+                uuid: Uuid::nil(),
+                name: "Default".to_string(),
+            }],
+        }
+    }
+}
+
+impl ProfileRegistry {
+    /// Loads the profile registry from the application data folder, or creates one containing only the default profile.
+    pub async fn load(root: PathBuf) -> Result<Self, Error> {
+        task::spawn_blocking(move || {
+            let path = registry_path(&root);
+
+            match fs::read_to_string(&path) {
+                Ok(data) => {
+                    let mut registry: Self = serde_json::from_str(&data)?;
+
+                    if registry.profiles.is_empty() {
+                        registry = Self::default();
+                    } else if registry.get(registry.active).is_none() {
+                        registry.active = registry.profiles[0].uuid;
+                    }
+
+                    Ok(registry)
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+                Err(error) => Err(error.into()),
+            }
+        })
+        .await?
+    }
+    /// Saves the profile registry to the application data folder, replacing the previous registry in a single step.
+    pub async fn save(&self, root: PathBuf) -> Result<(), Error> {
+        let data = serde_json::to_string_pretty(self)?;
+
+        task::spawn_blocking(move || {
+            let path = registry_path(&root);
+            let temp_path = path.with_extension("json.tmp");
+
+            fs::create_dir_all(root.join("Profiles"))?;
+            fs::write(&temp_path, data)?;
+            fs::rename(temp_path, path)?;
+
+            Ok(())
+        })
+        .await?
+    }
+    pub fn get(&self, id: Uuid) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.uuid == id)
+    }
+    /// Adds a new profile, without making it the active profile.
+    pub fn create(&mut self, name: &str) -> Result<Profile, Error> {
+        let profile = Profile {
+            uuid: Uuid::new_v4(),
+            name: validate_name(name)?,
+        };
+
+        self.profiles.push(profile.clone());
+
+        Ok(profile)
+    }
+    pub fn rename(&mut self, id: Uuid, name: &str) -> Result<(), Error> {
+        let name = validate_name(name)?;
+
+        let profile = self
+            .profiles
+            .iter_mut()
+            .find(|profile| profile.uuid == id)
+            .ok_or(Error::UnknownProfile(id))?;
+
+        profile.name = name;
+
+        Ok(())
+    }
+    /// Removes a profile from the registry. Its database must be deleted separately, using ``ProfileRegistry::delete_database``.
+    pub fn remove(&mut self, id: Uuid) -> Result<Profile, Error> {
+        if id == self.active {
+            return Err(Error::ActiveProfile);
+        }
+
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.uuid == id)
+            .ok_or(Error::UnknownProfile(id))?;
+
+        Ok(self.profiles.remove(index))
+    }
+    /// Returns the path of a profile's database.
+    pub fn database_path(root: &Path, id: Uuid) -> PathBuf {
+        if id.is_nil() {
+            root.join("Internal Database")
+        } else {
+            root.join("Profiles")
+                .join(Simple::from_uuid(id).encode_lower(&mut Uuid::encode_buffer()))
+        }
+    }
+    /// Deletes a profile's database, including all of its progress and settings.
+    pub async fn delete_database(root: PathBuf, id: Uuid) -> Result<(), Error> {
+        task::spawn_blocking(
+            move || match fs::remove_dir_all(Self::database_path(&root, id)) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+                _ => Ok(()),
+            },
+        )
+        .await?
+    }
+}
+
+fn registry_path(root: &Path) -> PathBuf {
+    root.join("Profiles").join("profiles.json")
+}
+
+fn validate_name(name: &str) -> Result<String, Error> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+
+    Ok(name.to_string())
+}
@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::{
+    super::course::{CourseMap, CourseMapRelationType},
+    CourseProgress,
+};
+
+/// Finds Courses which are ready to be studied next, based on the prerequisites specified in Course Maps.
+///
+/// A Course is recommended if it is not active or complete, and each of its required (non-optional) prerequisites has a completion of at least `threshold` (scaled by the relation's weight) in every Course Map which includes it. A Course Map prerequisite is as complete as the least complete Course within it, including the Courses of its nested Course Maps. Prerequisites which aren't completable Courses or Course Maps containing any are left out.
+///
+/// Courses are returned in the order they first appear in `course_maps`, with Courses which have more prerequisites first.
+pub fn recommend(
+    course_maps: &[CourseMap],
+    progress: &HashMap<Uuid, CourseProgress>,
+    active: &HashSet<Uuid>,
+    threshold: f32,
+) -> Vec<Uuid> {
+    let mut order = Vec::new();
+    let mut prerequisite_counts: HashMap<Uuid, usize> = HashMap::new();
+    let mut blocked = HashSet::new();
+
+    let mut nested = HashMap::new();
+
+    for course_map in course_maps {
+        collect_course_maps(course_map, &mut nested);
+    }
+
+    for course_map in course_maps {
+        for item in &course_map.courses {
+            let Some(item_progress) = progress.get(&item.uuid) else {
+                continue;
+            };
+
+            if active.contains(&item.uuid)
+                || item_progress
+                    .overall_completion()
+                    .is_none_or(|completion| completion >= 1.0)
+            {
+                continue;
+            }
+
+            let prerequisites: Vec<_> = item
+                .relations
+                .iter()
+                .filter(|r| r.r#type == CourseMapRelationType::Prerequisite && !r.optional)
+                .collect();
+
+            let satisfied = prerequisites.iter().all(|relation| {
+                prerequisite_completion(relation.uuid, &nested, progress).is_none_or(|completion| {
+                    completion >= threshold * relation.required_completion()
+                })
+            });
+
+            if !satisfied {
+                blocked.insert(item.uuid);
+            }
+
+            if let Some(count) = prerequisite_counts.get_mut(&item.uuid) {
+                *count = (*count).max(prerequisites.len());
+            } else {
+                prerequisite_counts.insert(item.uuid, prerequisites.len());
+                order.push(item.uuid);
+            }
+        }
+    }
+
+    order.retain(|uuid| !blocked.contains(uuid));
+
+    // Stable, so Courses with the same number of prerequisites stay in Course Map order
+    order.sort_by_key(|uuid| std::cmp::Reverse(prerequisite_counts[uuid]));
+
+    order
+}
+
+/// Adds `course_map` and each of its nested Course Maps to `course_maps`, keyed by UUID.
+fn collect_course_maps<'a>(
+    course_map: &'a CourseMap,
+    course_maps: &mut HashMap<Uuid, &'a CourseMap>,
+) {
+    course_maps.entry(course_map.uuid).or_insert(course_map);
+
+    for nested in course_map.nested.values() {
+        collect_course_maps(nested, course_maps);
+    }
+}
+
+/// Returns the completion of a prerequisite, which is either a Course or a Course Map.
+fn prerequisite_completion(
+    uuid: Uuid,
+    course_maps: &HashMap<Uuid, &CourseMap>,
+    progress: &HashMap<Uuid, CourseProgress>,
+) -> Option<f32> {
+    if let Some(course_progress) = progress.get(&uuid) {
+        return course_progress.overall_completion();
+    }
+
+    course_maps
+        .get(&uuid)?
+        .item_ids()
+        .into_iter()
+        .filter_map(|item| progress.get(&item)?.overall_completion())
+        .reduce(f32::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use uuid::Uuid;
+
+    use super::{
+        super::{CourseProgress, TextbookProgress},
+        recommend,
+    };
+    use crate::course::CourseMap;
+
+    const FIRST: Uuid = Uuid::from_u128(0x0a0a_0a0a_0a0a_0a0a_0a0a_0a0a_0a0a_0a0a);
+    const NESTED: Uuid = Uuid::from_u128(0x0b0b_0b0b_0b0b_0b0b_0b0b_0b0b_0b0b_0b0b);
+    const FOLLOWING: Uuid = Uuid::from_u128(0x0c0c_0c0c_0c0c_0c0c_0c0c_0c0c_0c0c_0c0c);
+
+    fn progress(completion: f32) -> CourseProgress {
+        CourseProgress {
+            completion: vec![Some(TextbookProgress {
+                overall_completion: completion,
+                chapter_completion: Vec::new(),
+            })],
+            time_spent_today: 0,
+        }
+    }
+
+    fn course_maps() -> Vec<CourseMap> {
+        let mut nested: CourseMap = toml::from_str(
+            r#"
+title = "Nested"
+courses = [{ uuid = "0a0a0a0a-0a0a-0a0a-0a0a-0a0a0a0a0a0a", label = "First" }]
+"#,
+        )
+        .unwrap();
+        nested.uuid = NESTED;
+
+        // The relation to an item which is neither a Course nor a Course Map is left out
+        let mut course_map: CourseMap = toml::from_str(
+            r#"
+title = "Course Map"
+
+[[courses]]
+uuid = "0b0b0b0b-0b0b-0b0b-0b0b-0b0b0b0b0b0b"
+label = "Nested"
+
+[[courses]]
+uuid = "0c0c0c0c-0c0c-0c0c-0c0c-0c0c0c0c0c0c"
+label = "Following"
+relations = [
+    { uuid = "0b0b0b0b-0b0b-0b0b-0b0b-0b0b0b0b0b0b", type = "Prerequisite" },
+    { uuid = "0d0d0d0d-0d0d-0d0d-0d0d-0d0d0d0d0d0d", type = "Prerequisite" },
+]
+"#,
+        )
+        .unwrap();
+        course_map.nested.insert(NESTED, nested);
+
+        vec![course_map]
+    }
+
+    #[test]
+    fn nested_course_map_prerequisites_need_their_courses() {
+        let course_maps = course_maps();
+
+        let mut completions = HashMap::from([(FIRST, progress(0.5)), (FOLLOWING, progress(0.0))]);
+
+        assert!(recommend(&course_maps, &completions, &HashSet::new(), 1.0).is_empty());
+
+        completions.insert(FIRST, progress(1.0));
+
+        assert_eq!(
+            recommend(&course_maps, &completions, &HashSet::new(), 1.0),
+            [FOLLOWING]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 import { invoke, convertFileSrc } from "@tauri-apps/api/core";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
 import { open } from "@tauri-apps/plugin-shell";
 
 const websiteURL = "https://school-of-life-project.github.io/Portal/";
@@ -20,6 +21,8 @@ export interface CourseMap {
 	uuid: string;
 	title: string;
 	description?: string;
+	orientation: "TopToBottom" | "LeftToRight";
+	layout: "Automatic" | "Standard" | "Layered";
 	courses: CourseMapCourse[];
 }
 
@@ -28,12 +31,17 @@ export interface CourseMapCourse {
 	label: string;
 	color?: string;
 	relations: CourseMapRelation[];
+	display: "Collapsed" | "Cluster" | "Inline";
+	width?: number;
+	height?: number;
 }
 
 export interface CourseMapRelation {
 	uuid: string;
 	type: string;
 	optional: boolean;
+	label?: string;
+	weight: number;
 }
 
 export interface Course {
@@ -41,9 +49,11 @@ export interface Course {
 	title: string;
 	description?: string;
 	books: Textbook[];
+	migrations?: Record<string, Record<string, string>>;
 }
 
 export interface Textbook {
+	id?: string;
 	label: string;
 	file: string;
 	chapters: Chapter[];
@@ -59,13 +69,39 @@ export interface SectionGroup {
 	sections: string[];
 }
 
+// Based on /src-tauri/src/course/validation.rs
+
+export type CourseDiagnostic =
+	| { type: "UnreadableTextbook"; book: number; cause: string }
+	| { type: "UnknownRoot"; book: number; chapter: number; href: string }
+	| {
+			type: "UnknownSection";
+			book: number;
+			chapter: number;
+			group: number;
+			href: string;
+	  }
+	| { type: "UncoveredEntry"; book: number; href: string; label: string }
+	| { type: "ConflictingTextbookId"; book: number; id: string }
+	| { type: "UnknownMigrationTextbook"; key: string }
+	| { type: "IgnoredMigration"; book: number; from: string }
+	| { type: "UnknownMigrationTarget"; book: number; from: string; to: string };
+
+export type CourseMapDiagnostic =
+	| { type: "DuplicateItem"; item: string }
+	| { type: "UnknownRelationTarget"; item: string; relation: string }
+	| { type: "SelfRelation"; item: string }
+	| { type: "PrerequisiteCycle"; path: string[] }
+	| { type: "NestedCycle"; path: string[] }
+	| { type: "UnreadableNestedMap"; item: string; cause: string };
+
 // Based on /src-tauri/src/progress/mod.rs
 
 type BackendDate = string;
 
 export interface CourseCompletionData {
 	time_spent: Record<BackendDate, number>;
-	books: Record<number, CourseCompletionTextbookData>;
+	books: Record<string, CourseCompletionTextbookData>;
 }
 
 export interface CourseCompletionTextbookData {
@@ -103,6 +139,18 @@ export interface Settings {
 	custom_book_css?: string;
 }
 
+export interface Profile {
+	uuid: string;
+	name: string;
+}
+
+export type ImportMode = "Merge" | "Replace";
+
+export interface ProfileRegistry {
+	active: string;
+	profiles: Profile[];
+}
+
 export async function getBackendDate(): Promise<BackendDate> {
 	try {
 		return await invoke("get_backend_date");
@@ -188,6 +236,15 @@ export async function openInternalDataDir(): Promise<void> {
 	}
 }
 
+export async function openExportDir(): Promise<void> {
+	try {
+		const path: string = await invoke("get_export_dir");
+		return await open(path);
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
 export async function openIssueTracker(newIssue: boolean): Promise<void> {
 	try {
 		if (newIssue) {
@@ -244,6 +301,32 @@ export async function getCourse(
 	}
 }
 
+export async function validateCourse(
+	uuid: string,
+): Promise<CourseDiagnostic[]> {
+	try {
+		return await invoke("validate_course", {
+			uuid,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function exportCourse(
+	uuid: string,
+	includeCourseMaps: boolean,
+): Promise<string> {
+	try {
+		return await invoke("export_course", {
+			uuid,
+			includeCourseMaps,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
 export async function setCourseCompletion(
 	course: Course,
 	completion: CourseCompletionData,
@@ -258,6 +341,91 @@ export async function setCourseCompletion(
 	}
 }
 
+export type AnnotationKind = "Highlight" | "Note" | "Bookmark";
+
+export interface Annotation {
+	uuid?: string;
+	kind: AnnotationKind;
+	book: string;
+	href: string;
+	cfi: string;
+	color?: string;
+	excerpt?: string;
+	note?: string;
+	created?: string;
+	modified?: string;
+}
+
+export async function getAnnotations(uuid: string): Promise<Annotation[]> {
+	try {
+		return await invoke("get_annotations", {
+			uuid,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function createAnnotation(
+	uuid: string,
+	annotation: Annotation,
+): Promise<Annotation> {
+	try {
+		return await invoke("create_annotation", {
+			uuid,
+			annotation,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function updateAnnotation(
+	uuid: string,
+	annotation: Annotation,
+): Promise<Annotation> {
+	try {
+		return await invoke("update_annotation", {
+			uuid,
+			annotation,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function deleteAnnotation(
+	uuid: string,
+	annotation: string,
+): Promise<null> {
+	try {
+		return await invoke("delete_annotation", {
+			uuid,
+			annotation,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function exportAnnotations(uuid: string): Promise<string> {
+	try {
+		return await invoke("export_annotations", {
+			uuid,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function onResourcesChanged(
+	handler: (changes: ResourceChanges) => void,
+): Promise<UnlistenFn> {
+	return await listen<ResourceChanges>("resources-changed", (event) => {
+		handler(event.payload);
+	});
+}
+
 export async function getActiveCourses(): Promise<string[]> {
 	try {
 		return await invoke("get_active_courses");
@@ -286,7 +454,51 @@ export async function getAll(): Promise<ListingResult> {
 
 export interface ListingResult {
 	courses: Array<[Course, CourseProgress]>;
-	course_maps: Array<[CourseMap, string]>;
+	course_maps: Array<RenderedCourseMap>;
+	imports: ArchiveImport[];
+	failures: LoadFailure[];
+	warnings: Error[];
+}
+
+export interface LoadFailure {
+	uuid: string;
+	kind: "Course" | "CourseMap";
+	path: string;
+	error: Error;
+}
+
+// Based on /src-tauri/src/course/storage.rs
+
+// The SVG is laid out as a grid if the Course Map couldn't be rendered normally
+export type RenderedCourseMap = [
+	CourseMap,
+	string,
+	RenderError | null,
+	CourseMapDiagnostic[],
+];
+
+export type RenderError =
+	| { type: "Panicked"; message: string }
+	| { type: "TimedOut"; timeout_ms: number };
+
+export interface ResourceChanges {
+	courses: string[];
+	course_maps: string[];
+	listing: boolean;
+}
+
+export interface ArchiveImport {
+	archive: string;
+	items: ImportedResource[];
+	error?: string;
+}
+
+export interface ImportedResource {
+	uuid: string;
+	kind: "Course" | "CourseMap";
+	title?: string;
+	status: "Added" | "Replaced" | "Failed";
+	error?: string;
 }
 
 export async function getActive(): Promise<Array<[Course, CourseProgress]>> {
@@ -297,6 +509,55 @@ export async function getActive(): Promise<Array<[Course, CourseProgress]>> {
 	}
 }
 
+export async function getRecommended(
+	threshold: number,
+): Promise<Array<[Course, CourseProgress]>> {
+	try {
+		return await invoke("get_recommended", {
+			threshold,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+// Based on /src-tauri/src/course/search.rs
+
+export interface SearchHit {
+	course: string;
+	book: number;
+	href: string;
+	label?: string;
+	snippet: string;
+	score: number;
+}
+
+export async function searchTextbooks(
+	query: string,
+	limit: number,
+): Promise<SearchHit[]> {
+	try {
+		return await invoke("search_textbooks", {
+			query,
+			limit,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function getCourseMapProgress(
+	uuid: string,
+): Promise<[string, RenderError | null]> {
+	try {
+		return await invoke("get_course_map_progress", {
+			uuid,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
 export async function getOverallProgress(): Promise<OverallProgress> {
 	try {
 		return await invoke("get_overall_progress");
@@ -375,6 +636,80 @@ export async function setSettings(settings?: Settings): Promise<null> {
 	}
 }
 
+export async function getProfiles(): Promise<ProfileRegistry> {
+	try {
+		return await invoke("get_profiles");
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function createProfile(name: string): Promise<Profile> {
+	try {
+		return await invoke("create_profile", {
+			name,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function switchProfile(uuid: string): Promise<null> {
+	try {
+		return await invoke("switch_profile", {
+			uuid,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function renameProfile(
+	uuid: string,
+	name: string,
+): Promise<null> {
+	try {
+		return await invoke("rename_profile", {
+			uuid,
+			name,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function deleteProfile(uuid: string): Promise<null> {
+	try {
+		return await invoke("delete_profile", {
+			uuid,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function exportProgress(): Promise<string> {
+	try {
+		return await invoke("export_progress");
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
+export async function importProgress(
+	data: string,
+	mode: ImportMode,
+): Promise<null> {
+	try {
+		return await invoke("import_progress", {
+			data,
+			mode,
+		});
+	} catch (error) {
+		throw convertBackendAsyncError(error);
+	}
+}
+
 export function applyTheme(settings: Settings) {
 	if (settings.custom_css) {
 		const element = document.createElement("style");
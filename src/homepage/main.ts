@@ -4,6 +4,7 @@ import {
 	getActive,
 	getBackendDate,
 	getOverallProgress,
+	getRecommended,
 	getSettings,
 } from "../bindings.ts";
 import { sortCourses } from "../util.ts";
@@ -21,12 +22,23 @@ const coursePromise = getActive().catch((error) => {
 	displayError(error);
 });
 
+// Prerequisites must be fully completed before a Course is recommended
+const recommendationThreshold = 1;
+const maxRecommendations = 3;
+
+const recommendedPromise = getRecommended(recommendationThreshold).catch(
+	(error) => {
+		displayError(error);
+	},
+);
+
 const progressPromise = getOverallProgress().catch((error) => {
 	displayError(error);
 });
 
 const courseContainer = document.getElementById("activeCourses");
 const progressContainer = document.getElementById("progressGraphs");
+const recommendedContainer = document.getElementById("recommendedCourses");
 const settings = await settingsPromise;
 
 if (courseContainer && progressContainer && settings) {
@@ -56,6 +68,25 @@ if (courseContainer && progressContainer && settings) {
 		}
 	});
 
+	recommendedPromise.then((courses) => {
+		if (!courses || courses.length == 0 || !recommendedContainer) {
+			return;
+		}
+
+		console.log(courses);
+
+		const fragment = document.createDocumentFragment();
+
+		for (const course of courses.slice(0, maxRecommendations)) {
+			fragment.appendChild(graphCourse(settings, course[0], course[1]));
+		}
+
+		recommendedContainer.innerHTML = "";
+		recommendedContainer.appendChild(fragment);
+
+		document.getElementById("recommendedHeader")?.removeAttribute("hidden");
+	});
+
 	progressPromise.then((progress) => {
 		if (!progress) {
 			return;
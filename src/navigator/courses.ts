@@ -3,11 +3,19 @@ import {
 	CourseCompletionTextbookData,
 	CourseProgress,
 	displayError,
+	exportCourse,
+	openExportDir,
 	setActiveCourses,
 	setCourseCompletion,
 } from "../bindings.ts";
 import { BookChapterGraph } from "../graphing/main.ts";
-import { isCompletable, isComplete, isStarted, sortCourses } from "../util.ts";
+import {
+	isCompletable,
+	isComplete,
+	isStarted,
+	sortCourses,
+	textbookKey,
+} from "../util.ts";
 
 const listingElements: Map<string, HTMLAnchorElement> = new Map();
 
@@ -286,12 +294,56 @@ function buildCourseInfo(course: Course, progress: CourseProgress) {
 		root.appendChild(optionsWrapper);
 	}
 
+	root.appendChild(buildShareOptions(course));
+
 	return root;
 }
 
+function buildShareOptions(course: Course) {
+	const shareWrapper = document.createElement("details");
+
+	const title = document.createElement("summary");
+	title.innerHTML = "📤 Share Course";
+	shareWrapper.appendChild(title);
+
+	const description = document.createElement("p");
+	description.innerText =
+		"Exported Courses can be imported by placing them in the User Resources folder.";
+	shareWrapper.appendChild(description);
+
+	const includeLabel = document.createElement("label");
+	const includeCourseMaps = document.createElement("input");
+	includeCourseMaps.type = "checkbox";
+	includeLabel.appendChild(includeCourseMaps);
+	includeLabel.appendChild(
+		document.createTextNode(" Include Course Maps which reference this Course"),
+	);
+	shareWrapper.appendChild(includeLabel);
+	shareWrapper.appendChild(document.createElement("br"));
+
+	const exportButton = document.createElement("button");
+	exportButton.type = "button";
+	exportButton.innerText = "📦 Export Course";
+	exportButton.addEventListener("click", () => {
+		exportButton.disabled = true;
+
+		exportCourse(course.uuid, includeCourseMaps.checked)
+			.then(() => openExportDir())
+			.catch((error) => {
+				displayError(error);
+			})
+			.finally(() => {
+				exportButton.disabled = false;
+			});
+	});
+	shareWrapper.appendChild(exportButton);
+
+	return shareWrapper;
+}
+
 function updateCourseCompletion(course: Course, completed: boolean) {
 	if (completed) {
-		const books: Record<number, CourseCompletionTextbookData> = {};
+		const books: Record<string, CourseCompletionTextbookData> = {};
 
 		let i = 0;
 		for (const textbook of course.books) {
@@ -307,7 +359,7 @@ function updateCourseCompletion(course: Course, completed: boolean) {
 				}
 			}
 
-			books[i] = {
+			books[textbookKey(course, i)] = {
 				completed_sections: sections,
 			};
 
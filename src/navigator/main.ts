@@ -1,11 +1,21 @@
 import {
+	ArchiveImport,
+	Course,
 	displayError,
+	Error,
 	getActiveCourses,
 	getAll,
+	ListingResult,
+	LoadFailure,
+	onResourcesChanged,
 	openDataDir,
+	searchTextbooks,
 } from "../bindings.ts";
 import { buildCourseListing } from "./courses.ts";
 import { buildCourseMapListing } from "./maps.ts";
+import { buildSearchResults } from "./search.ts";
+
+const SEARCH_LIMIT = 50;
 
 const listingPromise = getAll().catch((error) => {
 	displayError(error);
@@ -17,11 +27,21 @@ const activePromise = getActiveCourses().catch((error) => {
 
 const folderButton = document.getElementById("folderOpener");
 const refreshButton = document.getElementById("refreshButton");
+const searchForm = document.getElementById("searchForm");
+const searchInput = <HTMLInputElement | null>(
+	document.getElementById("searchInput")
+);
 const contentListing = document.getElementById("listingInner");
 const contentViewer = document.getElementById("contentViewer");
 const styleContainer = document.createElement("style");
 window.document.head.appendChild(styleContainer);
 
+const importSummary = document.createElement("div");
+const failureSummary = document.createElement("div");
+
+// Used to display the titles of Courses in search results
+const courseIndex: Map<string, Course> = new Map();
+
 function openFolder() {
 	openDataDir().catch((error) => {
 		displayError(error);
@@ -40,6 +60,151 @@ if (refreshButton) {
 	});
 }
 
+function buildImportSummary(imports: ArchiveImport[]): DocumentFragment {
+	const fragment = document.createDocumentFragment();
+
+	let courses = 0;
+	let courseMaps = 0;
+
+	for (const archive of imports) {
+		for (const item of archive.items) {
+			if (item.status == "Failed") {
+				continue;
+			}
+
+			if (item.kind == "Course") {
+				courses += 1;
+			} else {
+				courseMaps += 1;
+			}
+		}
+	}
+
+	if (courses > 0 || courseMaps > 0) {
+		const summary = document.createElement("p");
+		summary.innerText =
+			"📥 Imported " +
+			courses +
+			(courses == 1 ? " Course" : " Courses") +
+			" and " +
+			courseMaps +
+			(courseMaps == 1 ? " Course Map." : " Course Maps.");
+
+		fragment.appendChild(summary);
+	}
+
+	for (const archive of imports) {
+		if (archive.error) {
+			const warning = document.createElement("p");
+			warning.innerText = "⚠️ " + archive.error;
+
+			fragment.appendChild(warning);
+		}
+	}
+
+	return fragment;
+}
+
+function buildFailureSummary(
+	failures: LoadFailure[],
+	warnings: Error[],
+): DocumentFragment {
+	const fragment = document.createDocumentFragment();
+
+	for (const error of warnings) {
+		const warning = document.createElement("p");
+		warning.innerText = "⚠️ " + error.message + ": " + error.cause;
+
+		fragment.appendChild(warning);
+	}
+
+	for (const failure of failures) {
+		const warning = document.createElement("p");
+		warning.innerText =
+			"⚠️ " +
+			failure.error.message +
+			" (" +
+			failure.path +
+			"): " +
+			failure.error.cause;
+
+		fragment.appendChild(warning);
+	}
+
+	return fragment;
+}
+
+function buildListing(
+	listing: ListingResult,
+	activeCourses: string[],
+	contentListing: HTMLElement,
+	contentViewer: HTMLElement,
+) {
+	const [courseListing, courseMapping] = buildCourseListing(
+		listing.courses,
+		new Set(activeCourses),
+		contentViewer,
+		styleContainer,
+	);
+
+	const fragment = document.createDocumentFragment();
+
+	if (listing.course_maps.length > 0 && listing.courses.length > 0) {
+		fragment.appendChild(
+			buildCourseMapListing(
+				courseMapping,
+				listing.course_maps,
+				contentViewer,
+				styleContainer,
+			),
+		);
+	}
+
+	fragment.appendChild(courseListing);
+
+	contentListing.innerHTML = "";
+	contentListing.appendChild(fragment);
+
+	courseIndex.clear();
+	for (const [course] of listing.courses) {
+		courseIndex.set(course.uuid, course);
+	}
+
+	// Listings refreshed after an import don't contain any imports, so the previous summary is kept
+	if (listing.imports.length > 0) {
+		importSummary.innerHTML = "";
+		importSummary.appendChild(buildImportSummary(listing.imports));
+	}
+
+	failureSummary.innerHTML = "";
+	failureSummary.appendChild(
+		buildFailureSummary(listing.failures, listing.warnings),
+	);
+}
+
+if (searchForm && searchInput && contentViewer) {
+	searchForm.addEventListener("submit", (event) => {
+		event.preventDefault();
+
+		const query = searchInput.value.trim();
+
+		if (query.length == 0) {
+			return;
+		}
+
+		searchTextbooks(query, SEARCH_LIMIT)
+			.then((hits) => {
+				contentViewer.innerHTML = "";
+				contentViewer.appendChild(
+					buildSearchResults(query, hits, courseIndex),
+				);
+			})
+			.catch((error) => {
+				displayError(error);
+			});
+	});
+}
+
 if (contentListing && contentViewer) {
 	Promise.all([listingPromise, activePromise]).then(
 		async ([listing, activeCourses]) => {
@@ -49,30 +214,22 @@ if (contentListing && contentViewer) {
 
 			console.log(listing);
 
-			const [courseListing, courseMapping] = buildCourseListing(
-				listing.courses,
-				new Set(activeCourses),
-				contentViewer,
-				styleContainer,
-			);
-
-			const fragment = document.createDocumentFragment();
-
-			if (listing.course_maps.length > 0 && listing.courses.length > 0) {
-				fragment.appendChild(
-					buildCourseMapListing(
-						courseMapping,
-						listing.course_maps,
-						contentViewer,
-						styleContainer,
-					),
-				);
-			}
+			buildListing(listing, activeCourses, contentListing, contentViewer);
 
-			fragment.appendChild(courseListing);
+			contentViewer.prepend(importSummary, failureSummary);
 
-			contentListing.innerHTML = "";
-			contentListing.appendChild(fragment);
+			// Resources which change on disk are refreshed in place, leaving the currently displayed resource as-is
+			onResourcesChanged(() => {
+				Promise.all([getAll(), getActiveCourses()])
+					.then(([listing, activeCourses]) => {
+						buildListing(listing, activeCourses, contentListing, contentViewer);
+					})
+					.catch((error) => {
+						displayError(error);
+					});
+			}).catch((error) => {
+				displayError(error);
+			});
 		},
 	);
 }
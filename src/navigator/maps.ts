@@ -1,10 +1,19 @@
-import { Course, CourseMap, CourseProgress, displayError } from "../bindings";
+import {
+	Course,
+	CourseMap,
+	CourseMapDiagnostic,
+	CourseProgress,
+	displayError,
+	getCourseMapProgress,
+	RenderedCourseMap,
+	RenderError,
+} from "../bindings";
 import { isCompletable, isComplete, isStarted, sortCourseMaps } from "../util";
 import { displayCourse } from "./courses";
 
 export function buildCourseMapListing(
 	courseMapping: Map<string, [Course, CourseProgress]>,
-	courseMaps: [CourseMap, string][],
+	courseMaps: RenderedCourseMap[],
 	contentViewer: HTMLElement,
 	styleContainer: HTMLStyleElement,
 ): DocumentFragment {
@@ -19,9 +28,11 @@ export function buildCourseMapListing(
 
 	const list = document.createElement("ul");
 
-	const courseMapMap: Map<string, [CourseMap, string]> = new Map();
+	const courseMapMap: Map<string, RenderedCourseMap> = new Map();
+
+	for (const renderedCourseMap of courseMaps) {
+		const courseMap = renderedCourseMap[0];
 
-	for (const [courseMap, svg] of courseMaps) {
 		const element = document.createElement("li");
 		element.id = "map-" + courseMap.uuid;
 
@@ -32,7 +43,7 @@ export function buildCourseMapListing(
 
 		element.appendChild(label);
 
-		courseMapMap.set(courseMap.uuid, [courseMap, svg]);
+		courseMapMap.set(courseMap.uuid, renderedCourseMap);
 
 		list.appendChild(element);
 	}
@@ -45,20 +56,13 @@ export function buildCourseMapListing(
 			const courseMap = courseMapMap.get(identifier);
 
 			if (courseMap) {
-				contentViewer.innerHTML = "";
-				contentViewer.appendChild(
-					buildCourseMapInfo(
-						courseMap[0],
-						courseMap[1],
-						courseMapping,
-						courseMapMap,
-						contentViewer,
-						styleContainer,
-					),
+				displayCourseMap(
+					courseMap,
+					courseMapping,
+					courseMapMap,
+					contentViewer,
+					styleContainer,
 				);
-
-				styleContainer.innerHTML =
-					"#map-" + courseMap[0].uuid + " {font-weight: bold}";
 			}
 		}
 	};
@@ -79,11 +83,54 @@ export function buildCourseMapListing(
 	return fragment;
 }
 
+async function displayCourseMap(
+	renderedCourseMap: RenderedCourseMap,
+	courseMapping: Map<string, [Course, CourseProgress]>,
+	courseMapMap: Map<string, RenderedCourseMap>,
+	contentViewer: HTMLElement,
+	styleContainer: HTMLStyleElement,
+) {
+	const courseMap = renderedCourseMap[0];
+
+	let svg = renderedCourseMap[1];
+	let renderError = renderedCourseMap[2];
+	let showsProgress = false;
+
+	if (svg.length != 0) {
+		try {
+			[svg, renderError] = await getCourseMapProgress(courseMap.uuid);
+			showsProgress = true;
+		} catch {
+			// Fall back to the rendering without progress
+		}
+	}
+
+	contentViewer.innerHTML = "";
+	contentViewer.appendChild(
+		buildCourseMapInfo(
+			courseMap,
+			svg,
+			renderError,
+			renderedCourseMap[3],
+			showsProgress,
+			courseMapping,
+			courseMapMap,
+			contentViewer,
+			styleContainer,
+		),
+	);
+
+	styleContainer.innerHTML = "#map-" + courseMap.uuid + " {font-weight: bold}";
+}
+
 function buildCourseMapInfo(
 	courseMap: CourseMap,
 	svg: string,
+	renderError: RenderError | null,
+	diagnostics: CourseMapDiagnostic[],
+	showsProgress: boolean,
 	courseMapping: Map<string, [Course, CourseProgress]>,
-	courseMapMap: Map<string, [CourseMap, string]>,
+	courseMapMap: Map<string, RenderedCourseMap>,
 	contentViewer: HTMLElement,
 	styleContainer: HTMLStyleElement,
 ) {
@@ -99,10 +146,32 @@ function buildCourseMapInfo(
 		root.appendChild(description);
 	}
 
+	if (diagnostics.length > 0) {
+		root.appendChild(
+			buildDiagnosticList(courseMap, diagnostics, courseMapMap),
+		);
+	}
+
 	if (svg.length == 0) {
 		return root;
 	}
 
+	if (renderError) {
+		const warning = document.createElement("p");
+
+		if (renderError.type == "TimedOut") {
+			warning.innerText =
+				"⚠️ This Course Map took too long to lay out, so its items are displayed in a grid without relations.";
+		} else {
+			warning.innerText =
+				"⚠️ This Course Map could not be laid out (" +
+				renderError.message +
+				"), so its items are displayed in a grid without relations.";
+		}
+
+		root.appendChild(warning);
+	}
+
 	const image = document.createElement("div");
 	image.innerHTML = svg;
 
@@ -113,11 +182,17 @@ function buildCourseMapInfo(
 
 	const items: SVGForeignObjectElement[] = [];
 
+	// Items of nested Course Maps are placed within their own svg element
+	const parents: Map<SVGForeignObjectElement, Node> = new Map();
+
 	const elements = svgElement.getElementsByTagName("foreignObject");
 
 	while (elements.length > 0) {
 		for (const element of elements) {
-			svgElement.removeChild(element);
+			const parent = element.parentNode ?? svgElement;
+
+			parent.removeChild(element);
+			parents.set(element, parent);
 			items.push(element);
 		}
 	}
@@ -161,10 +236,11 @@ function buildCourseMapInfo(
 
 						paragraph.innerText = "📚 " + paragraph.innerText;
 
+						// Renderings which show progress already display completion
 						if (isCompletable(course[0])) {
-							if (isComplete(course[1])) {
+							if (!showsProgress && isComplete(course[1])) {
 								paragraph.innerText += " 🏆";
-							} else if (isStarted(course[1])) {
+							} else if (!showsProgress && isStarted(course[1])) {
 								paragraph.innerText += " ✏️";
 							}
 						} else {
@@ -188,7 +264,7 @@ function buildCourseMapInfo(
 			}
 		}
 
-		svgElement.appendChild(element);
+		(parents.get(element) ?? svgElement).appendChild(element);
 	}
 
 	const handleCourseDisplay = (identifier: string) => {
@@ -200,20 +276,13 @@ function buildCourseMapInfo(
 			const itemCourseMap = courseMapMap.get(identifier);
 
 			if (itemCourseMap) {
-				contentViewer.innerHTML = "";
-				contentViewer.appendChild(
-					buildCourseMapInfo(
-						itemCourseMap[0],
-						itemCourseMap[1],
-						courseMapping,
-						courseMapMap,
-						contentViewer,
-						styleContainer,
-					),
+				displayCourseMap(
+					itemCourseMap,
+					courseMapping,
+					courseMapMap,
+					contentViewer,
+					styleContainer,
 				);
-
-				styleContainer.innerHTML =
-					"#map-" + itemCourseMap[0].uuid + " {font-weight: bold}";
 			}
 		}
 	};
@@ -261,3 +330,83 @@ function buildCourseMapInfo(
 
 	return root;
 }
+
+function buildDiagnosticList(
+	courseMap: CourseMap,
+	diagnostics: CourseMapDiagnostic[],
+	courseMapMap: Map<string, RenderedCourseMap>,
+) {
+	const labels: Map<string, string> = new Map();
+
+	for (const item of courseMap.courses) {
+		labels.set(item.uuid, item.label);
+	}
+
+	const label = (uuid: string) => '"' + (labels.get(uuid) ?? uuid) + '"';
+	const mapTitle = (uuid: string) =>
+		'"' + (courseMapMap.get(uuid)?.[0].title ?? uuid) + '"';
+
+	const wrapper = document.createElement("details");
+
+	const title = document.createElement("summary");
+	title.innerText =
+		"⚠️ This Course Map has " +
+		diagnostics.length +
+		(diagnostics.length == 1 ? " problem" : " problems");
+	wrapper.appendChild(title);
+
+	const list = document.createElement("ul");
+
+	for (const diagnostic of diagnostics) {
+		const element = document.createElement("li");
+
+		switch (diagnostic.type) {
+			case "DuplicateItem":
+				element.innerText =
+					"Item " + label(diagnostic.item) + " is included more than once.";
+				break;
+			case "UnknownRelationTarget":
+				element.innerText =
+					"Item " +
+					label(diagnostic.item) +
+					" has a relation to " +
+					diagnostic.relation +
+					", which is not in this Course Map.";
+				break;
+			case "SelfRelation":
+				element.innerText =
+					"Item " + label(diagnostic.item) + " has a relation to itself.";
+				break;
+			case "PrerequisiteCycle":
+				element.innerText =
+					"Prerequisites form a cycle: " +
+					diagnostic.path.map(label).join(" → ") +
+					" → " +
+					label(diagnostic.path[0]) +
+					".";
+				break;
+			case "NestedCycle":
+				element.innerText =
+					"Course Maps include each other: " +
+					diagnostic.path.map(mapTitle).join(" → ") +
+					" → " +
+					mapTitle(diagnostic.path[0]) +
+					".";
+				break;
+			case "UnreadableNestedMap":
+				element.innerText =
+					"Item " +
+					label(diagnostic.item) +
+					" could not be loaded (" +
+					diagnostic.cause +
+					").";
+				break;
+		}
+
+		list.appendChild(element);
+	}
+
+	wrapper.appendChild(list);
+
+	return wrapper;
+}
@@ -0,0 +1,60 @@
+import { Course, SearchHit } from "../bindings.ts";
+
+export function buildSearchResults(
+	query: string,
+	hits: SearchHit[],
+	courses: Map<string, Course>,
+): DocumentFragment {
+	const fragment = document.createDocumentFragment();
+
+	const title = document.createElement("h2");
+	title.innerText = "🔍 " + query;
+	fragment.appendChild(title);
+
+	if (hits.length == 0) {
+		const message = document.createElement("p");
+		message.innerText =
+			"No textbooks contain this text. Textbooks are searched by whole words.";
+		fragment.appendChild(message);
+
+		return fragment;
+	}
+
+	const list = document.createElement("ul");
+	list.className = "search-results";
+
+	for (const hit of hits) {
+		const course = courses.get(hit.course);
+
+		if (!course) {
+			continue;
+		}
+
+		const params = new URLSearchParams();
+		params.set("uuid", hit.course);
+		params.set("document_index", String(hit.book));
+		params.set("href", hit.href);
+
+		const item = document.createElement("li");
+
+		const link = document.createElement("a");
+		link.href = "/viewer.html?" + params.toString();
+		link.innerText =
+			"📚 " +
+			course.title +
+			" › " +
+			(course.books[hit.book]?.label ?? "") +
+			(hit.label ? " › " + hit.label : "");
+		item.appendChild(link);
+
+		const snippet = document.createElement("p");
+		snippet.innerText = hit.snippet;
+		item.appendChild(snippet);
+
+		list.appendChild(item);
+	}
+
+	fragment.appendChild(list);
+
+	return fragment;
+}
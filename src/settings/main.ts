@@ -1,24 +1,38 @@
 import { getVersion } from "@tauri-apps/api/app";
 import {
+	createProfile,
+	deleteProfile,
 	displayError,
+	exportProgress,
+	getProfiles,
 	getSettings,
+	ImportMode,
+	importProgress,
 	openDataDir,
 	openDiscussionBoard,
+	openExportDir,
 	openInternalDataDir,
 	openIssueTracker,
 	openRepo,
 	openWebsite,
 	placeholderBookCSS,
 	placeholderThemeCSS,
+	ProfileRegistry,
+	renameProfile,
 	setActiveCourses,
 	setSettings,
 	Settings,
+	switchProfile,
 } from "../bindings";
 
 const settingsPromise = getSettings().catch((error) => {
 	displayError(error);
 });
 
+const profilesPromise = getProfiles().catch((error) => {
+	displayError(error);
+});
+
 const resetCoursesButton = document.getElementById("resetCoursesButton");
 const resetSettingsButton = document.getElementById("resetSettingsButton");
 const appWebsiteButton = document.getElementById("appWebsiteButton");
@@ -27,7 +41,14 @@ const appDiscussionButton = document.getElementById("appDiscussionButton");
 const appRepoButton = document.getElementById("appRepoButton");
 const resourceButton = document.getElementById("resourceButton");
 const internalFolderButton = document.getElementById("internalFolderButton");
+const exportProgressButton = document.getElementById("exportProgressButton");
+const mergeProgressButton = document.getElementById("mergeProgressButton");
+const replaceProgressButton = document.getElementById("replaceProgressButton");
+const progressFileInput = document.getElementById(
+	"progressFileInput",
+) as HTMLInputElement | null;
 const settingsForm = document.getElementById("settingsRoot");
+const profileRoot = document.getElementById("profileRoot");
 const appVersionLabel = document.getElementById("appVersionLabel");
 
 async function updateSettings(settings?: Settings) {
@@ -54,6 +75,44 @@ if (resetCoursesButton) {
 	});
 }
 
+async function restoreProgress(mode: ImportMode) {
+	const file = progressFileInput?.files?.item(0);
+
+	if (!file) {
+		return;
+	}
+
+	return file
+		.text()
+		.then((data) => importProgress(data, mode))
+		.then(() => location.reload())
+		.catch((error) => {
+			displayError(error);
+		});
+}
+
+if (exportProgressButton) {
+	exportProgressButton.addEventListener("click", () => {
+		exportProgress()
+			.then(() => openExportDir())
+			.catch((error) => {
+				displayError(error);
+			});
+	});
+}
+
+if (mergeProgressButton) {
+	mergeProgressButton.addEventListener("click", () => {
+		restoreProgress("Merge");
+	});
+}
+
+if (replaceProgressButton) {
+	replaceProgressButton.addEventListener("click", () => {
+		restoreProgress("Replace");
+	});
+}
+
 if (appWebsiteButton) {
 	appWebsiteButton.addEventListener("click", () => {
 		openWebsite().catch((error) => {
@@ -109,6 +168,15 @@ if (appVersionLabel) {
 	});
 }
 
+const profiles = await profilesPromise;
+
+if (profileRoot && profiles) {
+	const list = buildProfileList(profiles);
+
+	profileRoot.innerHTML = "";
+	profileRoot.appendChild(list);
+}
+
 const settings = await settingsPromise;
 
 console.log(settings);
@@ -120,6 +188,105 @@ if (settingsForm && settings) {
 	settingsForm.appendChild(form);
 }
 
+function buildProfileList(profiles: ProfileRegistry) {
+	const root = document.createDocumentFragment();
+
+	const list = document.createElement("ul");
+
+	for (const profile of profiles.profiles) {
+		const active = profile.uuid == profiles.active;
+
+		const item = document.createElement("li");
+
+		const nameInput = document.createElement("input");
+		nameInput.type = "text";
+		nameInput.value = profile.name;
+		nameInput.setAttribute("aria-label", "Profile name");
+		nameInput.addEventListener("change", () => {
+			renameProfile(profile.uuid, nameInput.value).catch((error) => {
+				nameInput.value = profile.name;
+				displayError(error);
+			});
+		});
+
+		item.appendChild(nameInput);
+
+		if (active) {
+			const label = document.createElement("span");
+			label.innerText = " ✅ Active";
+
+			item.appendChild(label);
+		} else {
+			const switchButton = document.createElement("button");
+			switchButton.type = "button";
+			switchButton.innerText = "🔀 Switch";
+			switchButton.addEventListener("click", () => {
+				switchProfile(profile.uuid)
+					.then(() => location.reload())
+					.catch((error) => {
+						displayError(error);
+					});
+			});
+
+			const deleteButton = document.createElement("button");
+			deleteButton.type = "button";
+			deleteButton.innerText = "🗑️ Delete";
+			deleteButton.addEventListener("click", () => {
+				deleteProfile(profile.uuid)
+					.then(() => location.reload())
+					.catch((error) => {
+						displayError(error);
+					});
+			});
+
+			item.appendChild(switchButton);
+			item.appendChild(deleteButton);
+		}
+
+		list.appendChild(item);
+	}
+
+	root.appendChild(list);
+
+	const details = document.createElement("details");
+
+	const summary = document.createElement("summary");
+	summary.innerText = "➕ New Profile";
+	details.appendChild(summary);
+
+	const newNameInput = document.createElement("input");
+	newNameInput.type = "text";
+	newNameInput.id = "newProfileName";
+	newNameInput.required = true;
+
+	const newNameLabel = document.createElement("label");
+	newNameLabel.setAttribute("for", "newProfileName");
+	newNameLabel.innerText = "👤 Name: ";
+
+	const createButton = document.createElement("button");
+	createButton.type = "button";
+	createButton.innerText = "➕ Create Profile";
+	createButton.addEventListener("click", () => {
+		if (newNameInput.value.trim().length == 0) {
+			return;
+		}
+
+		createProfile(newNameInput.value)
+			.then(() => location.reload())
+			.catch((error) => {
+				displayError(error);
+			});
+	});
+
+	details.appendChild(newNameLabel);
+	details.appendChild(newNameInput);
+	details.appendChild(createButton);
+
+	root.appendChild(details);
+
+	return root;
+}
+
 function buildSettingsForm(settings: Settings) {
 	const root = document.createDocumentFragment();
 
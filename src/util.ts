@@ -1,4 +1,4 @@
-import { Course, CourseMap, CourseProgress } from "./bindings";
+import { Course, CourseProgress, RenderedCourseMap } from "./bindings";
 
 export function sortCourses(
 	courses: [Course, CourseProgress][],
@@ -29,7 +29,7 @@ export function sortCourses(
 	});
 }
 
-export function sortCourseMaps(courseMaps: [CourseMap, string][]) {
+export function sortCourseMaps(courseMaps: RenderedCourseMap[]) {
 	courseMaps.sort((a, b) => {
 		const titleA = a[0].title;
 		const titleB = b[0].title;
@@ -72,6 +72,11 @@ export function isStarted(progress: CourseProgress): boolean {
 	return started;
 }
 
+// Based on Textbook::progress_key in /src-tauri/src/course/mod.rs
+export function textbookKey(course: Course, index: number): string {
+	return course.books[index]?.id ?? String(index);
+}
+
 export function isCompletable(course: Course): boolean {
 	let completable = false;
 
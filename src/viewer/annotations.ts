@@ -0,0 +1,298 @@
+import {
+	Annotation,
+	AnnotationKind,
+	createAnnotation,
+	deleteAnnotation,
+	displayError,
+	Error,
+	exportAnnotations,
+	getAnnotations,
+	openExportDir,
+	updateAnnotation,
+} from "../bindings.ts";
+import { Book, Contents, Rendition } from "epubjs";
+
+const HIGHLIGHT_COLORS: [string, string][] = [
+	["Yellow", "#ffd54f"],
+	["Green", "#81c784"],
+	["Blue", "#64b5f6"],
+	["Pink", "#f06292"],
+];
+
+const KIND_ICONS: Record<AnnotationKind, string> = {
+	Highlight: "🖍️",
+	Note: "📝",
+	Bookmark: "🔖",
+};
+
+interface AnnotationLocation {
+	href: string;
+	cfi: string;
+	excerpt?: string;
+}
+
+// Displays the learner's highlights, notes, and bookmarks within a textbook, and applies highlights to the rendered textbook
+export class AnnotationPanel {
+	element: HTMLDetailsElement;
+	#course: string;
+	#book: string;
+	#epub: Book;
+	#rendition: Rendition;
+	#location?: AnnotationLocation;
+	#selection?: AnnotationLocation;
+	#summary: HTMLElement;
+	#color: HTMLSelectElement;
+	#note: HTMLTextAreaElement;
+	#highlightButton: HTMLButtonElement;
+	#list: HTMLUListElement;
+	#annotations: Map<string, Annotation> = new Map();
+	constructor(course: string, book: string, epub: Book, rendition: Rendition) {
+		this.#course = course;
+		this.#book = book;
+		this.#epub = epub;
+		this.#rendition = rendition;
+
+		this.element = document.createElement("details");
+		this.element.className = "annotations";
+
+		this.#summary = document.createElement("summary");
+		this.element.appendChild(this.#summary);
+
+		const controls = document.createElement("div");
+		controls.className = "annotation-controls";
+
+		this.#color = document.createElement("select");
+		this.#color.title = "Highlight color";
+		for (const [label, color] of HIGHLIGHT_COLORS) {
+			const option = document.createElement("option");
+			option.value = color;
+			option.innerText = label;
+			this.#color.appendChild(option);
+		}
+		controls.appendChild(this.#color);
+
+		this.#note = document.createElement("textarea");
+		this.#note.placeholder = "Note";
+		this.#note.rows = 3;
+		controls.appendChild(this.#note);
+
+		this.#highlightButton = buildButton("🖍️ Highlight", () => {
+			if (this.#selection) {
+				this.#create("Highlight", this.#selection);
+			}
+		});
+		this.#highlightButton.title = "Highlight the selected text";
+		this.#highlightButton.disabled = true;
+		controls.appendChild(this.#highlightButton);
+
+		const noteButton = buildButton("📝 Add Note", () => {
+			const location = this.#selection ?? this.#location;
+
+			if (location && this.#note.value.trim()) {
+				this.#create("Note", location);
+			}
+		});
+		noteButton.title =
+			"Attach the note to the selected text or the current page";
+		controls.appendChild(noteButton);
+
+		const bookmarkButton = buildButton("🔖 Bookmark", () => {
+			if (this.#location) {
+				this.#create("Bookmark", this.#location);
+			}
+		});
+		bookmarkButton.title = "Bookmark the current page";
+		controls.appendChild(bookmarkButton);
+
+		const exportButton = buildButton("📤 Export", () => {
+			exportButton.disabled = true;
+
+			exportAnnotations(this.#course)
+				.then(() => openExportDir())
+				.catch((error: Error) => {
+					displayError(error);
+				})
+				.finally(() => {
+					exportButton.disabled = false;
+				});
+		});
+		exportButton.title =
+			"Export the annotations in every textbook of this Course as Markdown";
+		controls.appendChild(exportButton);
+
+		this.element.appendChild(controls);
+
+		this.#list = document.createElement("ul");
+		this.#list.className = "annotation-list";
+		this.element.appendChild(this.#list);
+
+		this.#updateSummary();
+
+		rendition.on("selected", (cfiRange: string, contents: Contents) => {
+			const excerpt = contents.window.getSelection()?.toString().trim();
+			const section = this.#epub.spine.get(cfiRange);
+
+			if (excerpt && section) {
+				this.#selection = { href: section.href, cfi: cfiRange, excerpt };
+				this.#highlightButton.disabled = false;
+			}
+		});
+
+		getAnnotations(this.#course)
+			.then((annotations) => {
+				for (const annotation of annotations) {
+					if (annotation.book == this.#book) {
+						this.#add(annotation);
+					}
+				}
+			})
+			.catch((error: Error) => {
+				displayError(error);
+			});
+	}
+	setLocation(href: string, cfi: string) {
+		this.#location = { href, cfi };
+	}
+	#create(kind: AnnotationKind, location: AnnotationLocation) {
+		const note = this.#note.value.trim();
+
+		createAnnotation(this.#course, {
+			kind,
+			book: this.#book,
+			href: location.href,
+			cfi: location.cfi,
+			color: kind == "Highlight" ? this.#color.value : undefined,
+			excerpt: location.excerpt,
+			note: note ? note : undefined,
+		})
+			.then((annotation) => {
+				this.#add(annotation);
+
+				this.#note.value = "";
+				this.#clearSelection();
+			})
+			.catch((error: Error) => {
+				displayError(error);
+			});
+	}
+	#add(annotation: Annotation) {
+		if (!annotation.uuid) {
+			return;
+		}
+
+		this.#annotations.set(annotation.uuid, annotation);
+
+		if (annotation.kind == "Highlight") {
+			this.#rendition.annotations.highlight(
+				annotation.cfi,
+				{},
+				() => {},
+				"annotation-highlight",
+				{
+					fill: annotation.color ?? HIGHLIGHT_COLORS[0][1],
+					"fill-opacity": "0.35",
+					"mix-blend-mode": "multiply",
+				},
+			);
+		}
+
+		this.#list.appendChild(this.#buildItem(annotation));
+		this.#updateSummary();
+	}
+	#remove(annotation: Annotation, item: HTMLLIElement) {
+		if (!annotation.uuid) {
+			return;
+		}
+
+		const uuid = annotation.uuid;
+
+		deleteAnnotation(this.#course, uuid)
+			.then(() => {
+				this.#annotations.delete(uuid);
+
+				if (annotation.kind == "Highlight") {
+					this.#rendition.annotations.remove(annotation.cfi, "highlight");
+				}
+
+				item.remove();
+				this.#updateSummary();
+			})
+			.catch((error: Error) => {
+				displayError(error);
+			});
+	}
+	#buildItem(annotation: Annotation): HTMLLIElement {
+		const item = document.createElement("li");
+
+		const link = document.createElement("a");
+		link.setAttribute("tabindex", "0");
+		link.setAttribute("role", "button");
+		link.innerText =
+			KIND_ICONS[annotation.kind] +
+			" " +
+			(annotation.excerpt ?? annotation.note ?? annotation.kind);
+		if (annotation.color) {
+			link.style.borderLeftColor = annotation.color;
+		}
+		link.addEventListener("click", (event) => {
+			this.#rendition.display(annotation.cfi);
+			event.preventDefault();
+		});
+		link.addEventListener("keydown", (event) => {
+			if (event.code == "Enter") {
+				this.#rendition.display(annotation.cfi);
+				event.preventDefault();
+			}
+		});
+		item.appendChild(link);
+
+		const note = document.createElement("textarea");
+		note.placeholder = "Note";
+		note.rows = 2;
+		note.value = annotation.note ?? "";
+		note.addEventListener("change", () => {
+			const value = note.value.trim();
+
+			updateAnnotation(this.#course, {
+				...annotation,
+				note: value ? value : undefined,
+			})
+				.then((updated) => {
+					Object.assign(annotation, updated);
+				})
+				.catch((error: Error) => {
+					displayError(error);
+				});
+		});
+		item.appendChild(note);
+
+		const deleteButton = buildButton("🗑️", () => {
+			this.#remove(annotation, item);
+		});
+		deleteButton.title = "Delete";
+		item.appendChild(deleteButton);
+
+		return item;
+	}
+	#clearSelection() {
+		this.#selection = undefined;
+		this.#highlightButton.disabled = true;
+
+		// @ts-expect-error getContents() returns an array, despite its type
+		for (const contents of this.#rendition.getContents() as Contents[]) {
+			contents.window.getSelection()?.removeAllRanges();
+		}
+	}
+	#updateSummary() {
+		this.#summary.innerText =
+			"🖍️ Annotations (" + this.#annotations.size.toString() + ")";
+	}
+}
+
+function buildButton(label: string, handler: () => void): HTMLButtonElement {
+	const button = document.createElement("button");
+	button.innerText = label;
+	button.addEventListener("click", handler);
+
+	return button;
+}
@@ -1,5 +1,7 @@
 import { Course, CourseCompletionData } from "../bindings.ts";
 import { ListingItem, ViewManager, DocumentViewer } from "./shared.ts";
+import { textbookKey } from "../util.ts";
+import { AnnotationPanel } from "./annotations.ts";
 import Epub, { Book, EpubCFI } from "epubjs";
 import { NavItem } from "epubjs/types/navigation";
 
@@ -118,11 +120,14 @@ interface InnerData {
 export class ePubViewer implements DocumentViewer {
 	course: Course;
 	document_index: number;
+	// The content document to open instead of the saved position, such as a search result
+	initial_href?: string;
 	rendered: boolean;
 	#inner: InnerData | undefined = undefined;
-	constructor(course: Course, document_index: number) {
+	constructor(course: Course, document_index: number, initial_href?: string) {
 		this.course = course;
 		this.document_index = document_index;
+		this.initial_href = initial_href;
 
 		this.rendered = false;
 	}
@@ -160,7 +165,9 @@ export class ePubViewer implements DocumentViewer {
 						);
 					}
 
-					let position = initialProgress.books[this.document_index]?.position;
+					let position = initialProgress.books[
+						textbookKey(this.course, this.document_index)
+					]?.position;
 
 					let positionData: PositionData | undefined;
 
@@ -179,6 +186,10 @@ export class ePubViewer implements DocumentViewer {
 						position = resolveNavUrl(book, book.navigation.toc[0].href);
 					}
 
+					if (this.initial_href) {
+						position = this.initial_href;
+					}
+
 					const renderPromise = new Promise(
 						(resolve: (value: void) => void) => {
 							view.render(
@@ -211,9 +222,19 @@ export class ePubViewer implements DocumentViewer {
 							rendition.manager.currentLocation();
 						}
 
+						const annotations = new AnnotationPanel(
+							this.course.uuid,
+							textbookKey(this.course, this.document_index),
+							book,
+							rendition,
+						);
+						view.container.listing.appendChild(annotations.element);
+
 						rendition.on("locationChanged", (location: EventLocation) => {
 							if (location.start) {
 								if (location.href) {
+									annotations.setLocation(location.href, location.start);
+
 									const chapter = getChapter(book, {
 										location_href: location.href,
 										location_cfi: location.start,
@@ -16,6 +16,7 @@ const settingsPromise = getSettings().catch((error) => {
 
 const params = new URLSearchParams(window.location.search);
 const identifier = params.get("uuid");
+const href = params.get("href") ?? undefined;
 let raw_document_index = params.get("document_index");
 
 if (!raw_document_index) {
@@ -77,7 +78,11 @@ if (
 
 			console.log(result);
 
-			const viewer: DocumentViewer = new ePubViewer(result[0], document_index);
+			const viewer: DocumentViewer = new ePubViewer(
+				result[0],
+				document_index,
+				href,
+			);
 
 			try {
 				return await viewer.render(viewManager, result[1]);
@@ -10,6 +10,7 @@ import {
 	Textbook,
 } from "../bindings.ts";
 import { TimeProgressMeter } from "../graphing/main.ts";
+import { textbookKey } from "../util.ts";
 
 export interface ListingItem {
 	label: string;
@@ -167,15 +168,15 @@ export class ViewManager {
 		course: EncapsulatedCourseTextbook,
 		listing: HTMLOListElement,
 	) {
-		if (!course.completion.books[course.document_index]) {
-			course.completion.books[course.document_index] = {
+		const key = textbookKey(course.course, course.document_index);
+
+		if (!course.completion.books[key]) {
+			course.completion.books[key] = {
 				completed_sections: [],
 			};
 		}
 
-		const completed = new Set(
-			course.completion.books[course.document_index].completed_sections,
-		);
+		const completed = new Set(course.completion.books[key].completed_sections);
 		const chapters: Map<string, Chapter> = new Map();
 		const checkboxes: Map<string, HTMLInputElement> = new Map();
 
@@ -315,7 +316,9 @@ export class ViewManager {
 		});
 
 		this.savePosition = function (position: string) {
-			course.completion.books[course.document_index].position = position;
+			course.completion.books[
+				textbookKey(course.course, course.document_index)
+			].position = position;
 		};
 
 		this.container.listing.append(listing);
@@ -411,8 +414,9 @@ function handleProgressUpdate(
 		}
 	}
 
-	course.completion.books[course.document_index].completed_sections =
-		Array.from(completed);
+	course.completion.books[
+		textbookKey(course.course, course.document_index)
+	].completed_sections = Array.from(completed);
 
 	return updated_chapter_completion;
 }